@@ -0,0 +1,344 @@
+use fractals::{
+    AbsVariant, Average, Block, ColorMode, Complex64, Formula, Fractal, FractalKernel, FractalType,
+    InstructionSet, PhoenixForm, Precision, RenderContext, Trap,
+};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// Times `z = z^2 + c` through the formula interpreter and bytecode VM
+// against the hand-written Mandelbrot kernels, on one thread over the
+// default view, then the other families with vector kernels on every
+// instruction set, then the two ways of sharing a frame between threads.
+// Run with `cargo bench`.
+
+const WIDTH: usize = 1280;
+const HEIGHT: usize = 720;
+const MAX_ITERATIONS: u16 = 256;
+const FRAMES: u32 = 5;
+// Rows of the tiles `Fractal::render` hands to its threads
+const TILE_ROWS: usize = 4;
+
+fn main() {
+    let formula = Formula::parse("z = z^2 + c").unwrap();
+    let mandelbrot = FractalType::Mandelbrot;
+    let custom = FractalType::Custom(formula);
+    // The vector kernels are added where the build has them
+    #[allow(unused_mut)]
+    let mut cases = vec![
+        (
+            "mandelbrot f64",
+            &mandelbrot,
+            Precision::F64,
+            InstructionSet::None,
+        ),
+        (
+            "mandelbrot f32",
+            &mandelbrot,
+            Precision::F32,
+            InstructionSet::None,
+        ),
+        (
+            "mandelbrot fixed",
+            &mandelbrot,
+            Precision::Fixed,
+            InstructionSet::None,
+        ),
+        (
+            "formula interpreter f64",
+            &custom,
+            Precision::F64,
+            InstructionSet::None,
+        ),
+    ];
+    #[cfg(feature = "simd-portable")]
+    cases.extend([
+        (
+            "mandelbrot f64 portable",
+            &mandelbrot,
+            Precision::F64,
+            InstructionSet::Portable,
+        ),
+        (
+            "mandelbrot f32 portable",
+            &mandelbrot,
+            Precision::F32,
+            InstructionSet::Portable,
+        ),
+    ]);
+    #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+    cases.extend([
+        (
+            "mandelbrot f64 sse",
+            &mandelbrot,
+            Precision::F64,
+            InstructionSet::SSE,
+        ),
+        (
+            "mandelbrot f32 sse",
+            &mandelbrot,
+            Precision::F32,
+            InstructionSet::SSE,
+        ),
+    ]);
+    #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+    if is_x86_feature_detected!("avx2") {
+        cases.extend([
+            (
+                "mandelbrot f64 avx",
+                &mandelbrot,
+                Precision::F64,
+                InstructionSet::AVX,
+            ),
+            (
+                "mandelbrot f32 avx",
+                &mandelbrot,
+                Precision::F32,
+                InstructionSet::AVX,
+            ),
+            (
+                "formula vm f64 avx",
+                &custom,
+                Precision::F64,
+                InstructionSet::AVX,
+            ),
+            (
+                "formula vm f32 avx",
+                &custom,
+                Precision::F32,
+                InstructionSet::AVX,
+            ),
+        ]);
+    }
+    #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+    if is_x86_feature_detected!("avx512f") {
+        cases.extend([
+            (
+                "mandelbrot f64 avx512",
+                &mandelbrot,
+                Precision::F64,
+                InstructionSet::AVX512,
+            ),
+            (
+                "mandelbrot f32 avx512",
+                &mandelbrot,
+                Precision::F32,
+                InstructionSet::AVX512,
+            ),
+        ]);
+    }
+
+    println!(
+        "{}x{}, {} iterations, {} frames",
+        WIDTH, HEIGHT, MAX_ITERATIONS, FRAMES
+    );
+    for (name, ftype, prec, iset) in cases {
+        let mut fractal = Fractal::new()
+            .set_window_dim(WIDTH as u16, HEIGHT as u16)
+            .set_max_iter(MAX_ITERATIONS)
+            .set_prec(prec)
+            .set_iset(iset)
+            .set_fractal(ftype.clone())
+            .set_pow(2);
+        println!("{:<26}{:>9.2} ms/frame", name, time(&mut fractal));
+    }
+    families();
+    scheduler();
+}
+
+// Milliseconds a frame of `fractal` takes on one thread
+fn time(fractal: &mut Fractal) -> f64 {
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        fractal.render(0, 0);
+    }
+    start.elapsed().as_secs_f64() * 1e3 / FRAMES as f64
+}
+
+// Before and after the families moved onto `Lanes`, the least ms/frame of
+// 25 frames in 5 runs taken in turns on one thread of an AVX-512 CPU with
+// FMA, where the same frame varies by up to 20% between runs. The colorings
+// had no SSE kernels, AVX-512 ran the AVX kernels and Portable the scalar
+// ones. Phoenix at f32 spends most of its time on subnormals, which the
+// fused AVX-512 lanes take more assists for, its AVX kernel does not fuse.
+//
+//                        scalar        sse        avx     avx512   portable
+// celtic f64            243/229    131/126      68/69      72/41     236/64
+// celtic f32            237/217      66/62      37/36      36/22     229/38
+// phoenix f64           343/333    151/149      78/81      78/56     362/91
+// phoenix f32           905/833    274/241    171/152    151/164    900/313
+// orbit trap f64        319/301    319/172      88/93      84/81    299/149
+// orbit trap f32        290/309     309/98      52/58      51/40    292/139
+// stripe average f64  2055/2381  1995/1852    714/680    763/295  2625/1510
+// stripe average f32  1564/1722   1481/605    242/249    247/139   1421/739
+// lighting f64          388/410    369/299    178/164    177/105    394/235
+// lighting f32          525/540    533/163    103/109     111/79    559/145
+fn families() {
+    let cases = [
+        (
+            "celtic",
+            FractalType::AbsVariation(AbsVariant::Celtic),
+            ColorMode::Iteration,
+        ),
+        (
+            "phoenix",
+            FractalType::Phoenix(PhoenixForm::Classic),
+            ColorMode::Iteration,
+        ),
+        (
+            "orbit trap",
+            FractalType::Mandelbrot,
+            ColorMode::OrbitTrap(Trap::Point(0.0, 0.0)),
+        ),
+        (
+            "stripe average",
+            FractalType::Mandelbrot,
+            ColorMode::Average(Average::Stripe(5.0)),
+        ),
+        (
+            "lighting",
+            FractalType::Mandelbrot,
+            ColorMode::Lighting(0.8, 1.5),
+        ),
+    ];
+    // Instruction sets the CPU lacks render with the widest it has
+    #[allow(unused_mut)]
+    let mut isets = vec![("scalar", InstructionSet::None)];
+    #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+    isets.extend([
+        ("sse", InstructionSet::SSE),
+        ("avx", InstructionSet::AVX),
+        ("avx512", InstructionSet::AVX512),
+    ]);
+    #[cfg(feature = "simd-portable")]
+    isets.push(("portable", InstructionSet::Portable));
+
+    println!("\nother families, ms/frame");
+    print!("{:<20}", "");
+    for (name, _) in &isets {
+        print!("{:>10}", name);
+    }
+    println!();
+    for (name, ftype, color) in cases {
+        for (prec, bits) in [(Precision::F64, "f64"), (Precision::F32, "f32")] {
+            print!("{:<20}", format!("{} {}", name, bits));
+            for &(_, iset) in &isets {
+                let mut fractal = Fractal::new()
+                    .set_window_dim(WIDTH as u16, HEIGHT as u16)
+                    .set_max_iter(MAX_ITERATIONS)
+                    .set_prec(prec)
+                    .set_iset(iset)
+                    .set_color_mode(color)
+                    .set_fractal(ftype.clone())
+                    .set_pow(2);
+                print!("{:>10.2}", time(&mut fractal));
+            }
+            println!();
+        }
+    }
+}
+
+// Scalar `z = z^2 + c` over the view of the built-in Mandelbrot kernels,
+// adding the time every row takes to `rows`
+struct Timed {
+    rows: Arc<Mutex<Vec<f64>>>,
+}
+
+impl FractalKernel for Timed {
+    fn render(&self, ctx: RenderContext) -> Block {
+        let RenderContext {
+            rows,
+            width,
+            height,
+            max_iter,
+            mut block,
+            ..
+        } = ctx;
+        let h = height as f64;
+        for ycoord in rows.clone() {
+            let start = Instant::now();
+            let y = ycoord as f64 / h * 2.0 - 1.0;
+            for xcoord in 0..width {
+                let a = Complex64::new(xcoord as f64 / h * 2.0 - 1.8, y);
+                let mut z = a;
+                let mut iterations = 0;
+                while iterations < max_iter && z.abs_sq() < 4.0 {
+                    z = z * z + &a;
+                    iterations += 1;
+                }
+                block[ycoord - rows.start][xcoord] = iterations;
+            }
+            self.rows.lock().unwrap()[ycoord] += start.elapsed().as_secs_f64() * 1e3;
+        }
+        (rows.start, rows.end)
+    }
+}
+
+// Fixed strips, one per thread, against the tiles the pool threads of
+// `Fractal::render` and `Fractal::render_job` take in turn. The strips
+// through the main cardioid take longest. Wall times need as many cores as threads, so the frame is also
+// timed row by row and both schedules are laid out over those costs, which
+// gives the frame time each would reach with enough cores.
+fn scheduler() {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get().min(255)) as u8;
+    let rows = Arc::new(Mutex::new(vec![0.0; HEIGHT]));
+    let mut fractal = Fractal::new()
+        .set_window_dim(WIDTH as u16, HEIGHT as u16)
+        .set_max_iter(MAX_ITERATIONS)
+        .register(
+            "timed",
+            Precision::F64,
+            InstructionSet::None,
+            Timed { rows: rows.clone() },
+        )
+        .set_prec(Precision::F64)
+        .set_fractal(FractalType::Registered("timed".to_string()));
+
+    // One thread first, so no row waits on a core and the row times hold
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        fractal.render(0, 0);
+    }
+    let ms = start.elapsed().as_secs_f64() * 1e3 / FRAMES as f64;
+    let rows: Vec<f64> = rows
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|ms| ms / FRAMES as f64)
+        .collect();
+
+    println!("\nscalar mandelbrot f64 frames");
+    println!("{:<26}{:>9.2} ms/frame", "1 thread", ms);
+    let mut fractal = fractal.set_num_threads(cores);
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        fractal.render(0, 0);
+    }
+    let ms = start.elapsed().as_secs_f64() * 1e3 / FRAMES as f64;
+    println!("{:<26}{:>9.2} ms/frame", format!("tiles on {}", cores), ms);
+
+    let tiles: Vec<f64> = rows
+        .chunks(TILE_ROWS)
+        .map(|tile| tile.iter().sum())
+        .collect();
+    println!("\nlaid out over the row costs, ms/frame");
+    println!("{:<10}{:>10}{:>10}", "threads", "strips", "tiles");
+    for threads in [1, 2, 4, 8, 16] {
+        let height = HEIGHT / threads;
+        let strips = (0..threads)
+            .map(|i| match i + 1 == threads {
+                true => rows[i * height..].iter().sum(),
+                false => rows[i * height..(i + 1) * height].iter().sum(),
+            })
+            .fold(0.0, f64::max);
+        // Every tile goes to the thread that is free first
+        let mut busy = vec![0.0f64; threads];
+        for tile in &tiles {
+            let first = (0..threads)
+                .min_by(|&a, &b| busy[a].total_cmp(&busy[b]))
+                .unwrap();
+            busy[first] += tile;
+        }
+        let tiles = busy.into_iter().fold(0.0, f64::max);
+        println!("{:<10}{:>10.2}{:>10.2}", threads, strips, tiles);
+    }
+}
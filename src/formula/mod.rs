@@ -0,0 +1,250 @@
+use crate::utils::complex::Complex64;
+use std::fmt;
+use std::sync::Arc;
+mod parser;
+#[cfg(test)]
+mod tests;
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+pub mod vm;
+
+// A formula is a list of clauses, one per line or separated by `;`:
+//
+//  init: z = 0, w = c       evaluated once per pixel, optional
+//  z = z^3 + c*sin(z)       evaluated every iteration, in order
+//  bailout: |z| < 2         iterate while this holds, optional
+//
+// `z` starts at the pixel unless `init` sets it, `c` is the pixel or, in
+// Julia mode, the seed, and `pixel` is always the pixel. Any other name
+// assigned somewhere is a variable of its own, reading 0 until its first
+// assignment. `||` and `&&` combine comparisons, which look at real parts
+// only, so `|z|`, `re(z)` and `im(z)` are what bailouts compare. Without a
+// bailout the formula runs while |z| < 2.
+//
+// Formulas are run either by `Evaluator`, walking the tree one pixel at a
+// time, or on x86 by `vm::Vm`, running the compiled `vm::Bytecode` over a
+// vector of pixels.
+
+/// Slots of the variables every formula can read
+const FIXED: [&str; 3] = ["z", "c", "pixel"];
+const Z: usize = 0;
+const C: usize = 1;
+const PIXEL: usize = 2;
+
+/// Where and why a formula failed to parse, lines and columns start at 1
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Func {
+    Exp,
+    Log,
+    Sin,
+    Cos,
+    Sinh,
+    Cosh,
+    Abs,
+    Arg,
+    Re,
+    Im,
+    Conj,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CmpOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Const(Complex64),
+    /// Index into `Program::vars`
+    Var(usize),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(Func, Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+pub enum Cond {
+    Cmp(CmpOp, Expr, Expr),
+    And(Box<Cond>, Box<Cond>),
+    Or(Box<Cond>, Box<Cond>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Assign {
+    pub var: usize,
+    pub expr: Expr,
+}
+
+/// Parsed form of a formula, variables are resolved to slots
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub vars: Vec<String>,
+    pub init: Vec<Assign>,
+    pub step: Vec<Assign>,
+    pub bailout: Cond,
+}
+
+/// A parsed formula, cheap to clone so it can ride along with every frame
+#[derive(Clone, Debug)]
+pub struct Formula {
+    source: Arc<str>,
+    program: Arc<Program>,
+    #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+    bytecode: Arc<vm::Bytecode>,
+}
+
+impl Formula {
+    pub fn parse(src: &str) -> Result<Self, ParseError> {
+        let program = parser::parse(src)?;
+        Ok(Formula {
+            source: src.into(),
+            #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+            bytecode: Arc::new(vm::compile(&program)),
+            program: Arc::new(program),
+        })
+    }
+
+    #[inline]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+    #[inline]
+    pub(crate) fn bytecode(&self) -> &vm::Bytecode {
+        &self.bytecode
+    }
+
+    /// Evaluation state for one thread, reused from pixel to pixel
+    pub(crate) fn evaluator(&self) -> Evaluator<'_> {
+        Evaluator {
+            program: &self.program,
+            vars: vec![Complex64::new(0.0, 0.0); self.program.vars.len()],
+        }
+    }
+}
+
+pub struct Evaluator<'a> {
+    program: &'a Program,
+    vars: Vec<Complex64>,
+}
+
+impl<'a> Evaluator<'a> {
+    /// Number of iterations the bailout held for, up to `max_iterations`
+    pub fn escape_time(&mut self, pixel: Complex64, c: Complex64, max_iterations: u32) -> u32 {
+        for var in self.vars.iter_mut() {
+            *var = Complex64::new(0.0, 0.0);
+        }
+        self.vars[Z] = pixel;
+        self.vars[C] = c;
+        self.vars[PIXEL] = pixel;
+        for assign in &self.program.init {
+            self.vars[assign.var] = self.eval(&assign.expr);
+        }
+
+        let mut iterations = 0;
+        while iterations < max_iterations && self.holds(&self.program.bailout) {
+            for assign in &self.program.step {
+                self.vars[assign.var] = self.eval(&assign.expr);
+            }
+            iterations += 1;
+        }
+        iterations
+    }
+
+    fn holds(&self, cond: &Cond) -> bool {
+        match cond {
+            Cond::Cmp(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.eval(lhs).real, self.eval(rhs).real);
+                match op {
+                    CmpOp::Lt => lhs < rhs,
+                    CmpOp::Le => lhs <= rhs,
+                    CmpOp::Gt => lhs > rhs,
+                    CmpOp::Ge => lhs >= rhs,
+                }
+            }
+            Cond::And(lhs, rhs) => self.holds(lhs) && self.holds(rhs),
+            Cond::Or(lhs, rhs) => self.holds(lhs) || self.holds(rhs),
+        }
+    }
+
+    fn eval(&self, expr: &Expr) -> Complex64 {
+        match expr {
+            Expr::Const(value) => *value,
+            Expr::Var(var) => self.vars[*var],
+            Expr::Neg(arg) => -self.eval(arg),
+            // Only constant exponents are whole powers, as in `vm::compile`
+            Expr::Binary(BinOp::Pow, lhs, rhs) if !matches!(**rhs, Expr::Const(_)) => {
+                self.eval(lhs).powc(self.eval(rhs))
+            }
+            Expr::Binary(op, lhs, rhs) => binary(*op, self.eval(lhs), self.eval(rhs)),
+            Expr::Call(func, arg) => call(*func, self.eval(arg)),
+        }
+    }
+}
+
+/// Whole exponent of `z^w`, if `w` has one
+fn whole_power(w: Complex64) -> Option<i64> {
+    if w.img == 0.0 && w.real.fract() == 0.0 && w.real.abs() <= u32::MAX as f64 {
+        Some(w.real as i64)
+    } else {
+        None
+    }
+}
+
+fn binary(op: BinOp, lhs: Complex64, rhs: Complex64) -> Complex64 {
+    match op {
+        BinOp::Add => lhs + &rhs,
+        BinOp::Sub => lhs - &rhs,
+        BinOp::Mul => lhs * rhs,
+        BinOp::Div => lhs / rhs,
+        // Whole powers stay exact, the rest go through the principal branch
+        BinOp::Pow => match whole_power(rhs) {
+            Some(n) if n >= 0 => lhs.ipow(n as u32),
+            Some(n) => Complex64::new(1.0, 0.0) / lhs.ipow(-n as u32),
+            None => lhs.powc(rhs),
+        },
+    }
+}
+
+fn call(func: Func, arg: Complex64) -> Complex64 {
+    match func {
+        Func::Exp => arg.exp(),
+        Func::Log => arg.log(),
+        Func::Sin => arg.sin(),
+        Func::Cos => arg.cos(),
+        Func::Sinh => arg.sinh(),
+        Func::Cosh => arg.cosh(),
+        Func::Abs => Complex64::new(arg.abs(), 0.0),
+        Func::Arg => Complex64::new(arg.arg(), 0.0),
+        Func::Re => Complex64::new(arg.real, 0.0),
+        Func::Im => Complex64::new(arg.img, 0.0),
+        Func::Conj => arg.conjugate(),
+    }
+}
@@ -0,0 +1,467 @@
+use crate::utils::complex::Complex64;
+
+use super::{binary, call, Assign, BinOp, CmpOp, Cond, Expr, Func, ParseError, Program, FIXED, Z};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Num(f64),
+    /// Imaginary literal such as `2i` or `0.5i`
+    Imag(f64),
+    Ident(String),
+    Op(&'static str),
+    Newline,
+    Eof,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    tok: Tok,
+    line: usize,
+    column: usize,
+}
+
+// Longest operators first so `<=` is not read as `<` followed by `=`. There
+// is no `||`, the parser reads two adjacent `|` as one so that nested
+// absolute values such as `||z| - 1|` lex.
+const OPS: [&str; 17] = [
+    "<=", ">=", "&&", "+", "-", "*", "/", "^", "(", ")", "|", ",", "=", ":", ";", "<", ">",
+];
+
+fn lex(src: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    for (l, line) in src.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let ch = chars[i];
+            let column = i + 1;
+            if ch == '#' {
+                break;
+            }
+            if ch.is_whitespace() {
+                i += 1;
+                continue;
+            }
+            let tok = if ch.is_ascii_digit() || ch == '.' {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                // An exponent only when digits follow, otherwise the number
+                // ends before the `e`
+                if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                    let mut j = i + 1;
+                    if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                        j += 1;
+                    }
+                    if j < chars.len() && chars[j].is_ascii_digit() {
+                        i = j;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+                let text: String = chars[start..i].iter().collect();
+                let value = text.parse::<f64>().map_err(|_| ParseError {
+                    line: l + 1,
+                    column,
+                    message: format!("invalid number `{}`", text),
+                })?;
+                let imaginary = i < chars.len()
+                    && chars[i] == 'i'
+                    && !chars
+                        .get(i + 1)
+                        .is_some_and(|c| c.is_alphanumeric() || *c == '_');
+                if imaginary {
+                    i += 1;
+                    Tok::Imag(value)
+                } else {
+                    Tok::Num(value)
+                }
+            } else if ch.is_alphabetic() || ch == '_' {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                Tok::Ident(chars[start..i].iter().collect())
+            } else {
+                let rest: String = chars[i..].iter().take(2).collect();
+                match OPS.iter().find(|op| rest.starts_with(**op)) {
+                    Some(op) => {
+                        i += op.len();
+                        Tok::Op(op)
+                    }
+                    None => {
+                        return Err(ParseError {
+                            line: l + 1,
+                            column,
+                            message: format!("unexpected character `{}`", ch),
+                        })
+                    }
+                }
+            };
+            tokens.push(Token {
+                tok,
+                line: l + 1,
+                column,
+            });
+        }
+        tokens.push(Token {
+            tok: Tok::Newline,
+            line: l + 1,
+            column: chars.len() + 1,
+        });
+    }
+    // At the end of the last line, where a missing statement would go
+    let (line, column) = tokens.last().map_or((1, 1), |t| (t.line, t.column));
+    tokens.push(Token {
+        tok: Tok::Eof,
+        line,
+        column,
+    });
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    vars: Vec<String>,
+}
+
+pub fn parse(src: &str) -> Result<Program, ParseError> {
+    let tokens = lex(src)?;
+    // Every name assigned anywhere is a variable from the start, so it can
+    // be read before its assignment and reads 0 until then
+    let mut vars: Vec<String> = FIXED.iter().map(|name| name.to_string()).collect();
+    for pair in tokens.windows(2) {
+        if let (Tok::Ident(name), Tok::Op("=")) = (&pair[0].tok, &pair[1].tok) {
+            if !vars.contains(name) && constant(name).is_none() {
+                vars.push(name.clone());
+            }
+        }
+    }
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        vars,
+    };
+    parser.program()
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.tok != Tok::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, token: &Token, message: String) -> Result<T, ParseError> {
+        Err(ParseError {
+            line: token.line,
+            column: token.column,
+            message,
+        })
+    }
+
+    fn eat(&mut self, op: &'static str) -> bool {
+        if self.peek().tok == Tok::Op(op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: &'static str) -> Result<(), ParseError> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            let token = self.peek().clone();
+            self.error(&token, format!("expected `{}`, found {}", op, describe(&token.tok)))
+        }
+    }
+
+    fn program(&mut self) -> Result<Program, ParseError> {
+        let mut init = Vec::new();
+        let mut step = Vec::new();
+        let mut bailout = None;
+
+        loop {
+            while self.peek().tok == Tok::Newline || self.peek().tok == Tok::Op(";") {
+                self.pos += 1;
+            }
+            let token = self.peek().clone();
+            let keyword = match &token.tok {
+                Tok::Eof => break,
+                Tok::Ident(name) if self.tokens[self.pos + 1].tok == Tok::Op(":") => {
+                    Some(name.clone())
+                }
+                _ => None,
+            };
+            match keyword.as_deref() {
+                Some("init") => {
+                    self.pos += 2;
+                    loop {
+                        init.push(self.assign()?);
+                        if !self.eat(",") {
+                            break;
+                        }
+                    }
+                }
+                Some("bailout") => {
+                    if bailout.is_some() {
+                        return self.error(&token, "bailout is already given".to_string());
+                    }
+                    self.pos += 2;
+                    bailout = Some(self.or()?);
+                }
+                Some(other) => {
+                    return self.error(
+                        &token,
+                        format!("unknown clause `{}`, expected `init` or `bailout`", other),
+                    )
+                }
+                None => step.push(self.assign()?),
+            }
+            let token = self.peek().clone();
+            match token.tok {
+                Tok::Newline | Tok::Op(";") | Tok::Eof => {}
+                _ => {
+                    return self.error(
+                        &token,
+                        format!("expected end of statement, found {}", describe(&token.tok)),
+                    )
+                }
+            }
+        }
+
+        if step.is_empty() {
+            let token = self.peek().clone();
+            return self.error(&token, "formula has no iteration statement".to_string());
+        }
+        let bailout = bailout.unwrap_or(Cond::Cmp(
+            CmpOp::Lt,
+            Expr::Call(Func::Abs, Box::new(Expr::Var(Z))),
+            Expr::Const(Complex64::new(2.0, 0.0)),
+        ));
+        Ok(Program {
+            vars: self.vars.clone(),
+            init,
+            step,
+            bailout,
+        })
+    }
+
+    fn assign(&mut self) -> Result<Assign, ParseError> {
+        let token = self.next();
+        let name = match &token.tok {
+            Tok::Ident(name) => name.clone(),
+            other => {
+                return self.error(&token, format!("expected a variable, found {}", describe(other)))
+            }
+        };
+        if (FIXED.contains(&name.as_str()) && name != "z") || constant(&name).is_some() {
+            return self.error(&token, format!("`{}` cannot be assigned", name));
+        }
+        self.expect("=")?;
+        let expr = self.expr()?;
+        let var = self.vars.iter().position(|v| *v == name);
+        let var = var.expect("assigned names are collected before parsing");
+        Ok(Assign { var, expr })
+    }
+
+    // `||`, two `|` with nothing between them
+    fn eat_or(&mut self) -> bool {
+        let (first, second) = match &self.tokens[self.pos..] {
+            [first, second, ..] => (first, second),
+            _ => return false,
+        };
+        let adjacent = first.line == second.line && first.column + 1 == second.column;
+        if first.tok == Tok::Op("|") && second.tok == Tok::Op("|") && adjacent {
+            self.pos += 2;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Cond, ParseError> {
+        let mut cond = self.and()?;
+        while self.eat_or() {
+            cond = Cond::Or(Box::new(cond), Box::new(self.and()?));
+        }
+        Ok(cond)
+    }
+
+    fn and(&mut self) -> Result<Cond, ParseError> {
+        let mut cond = self.cmp()?;
+        while self.eat("&&") {
+            cond = Cond::And(Box::new(cond), Box::new(self.cmp()?));
+        }
+        Ok(cond)
+    }
+
+    fn cmp(&mut self) -> Result<Cond, ParseError> {
+        let lhs = self.expr()?;
+        let token = self.next();
+        let op = match token.tok {
+            Tok::Op("<") => CmpOp::Lt,
+            Tok::Op("<=") => CmpOp::Le,
+            Tok::Op(">") => CmpOp::Gt,
+            Tok::Op(">=") => CmpOp::Ge,
+            ref other => {
+                return self.error(
+                    &token,
+                    format!("expected a comparison, found {}", describe(other)),
+                )
+            }
+        };
+        Ok(Cond::Cmp(op, lhs, self.expr()?))
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.term()?;
+        loop {
+            let op = if self.eat("+") {
+                BinOp::Add
+            } else if self.eat("-") {
+                BinOp::Sub
+            } else {
+                return Ok(expr);
+            };
+            expr = binary_expr(op, expr, self.term()?);
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = if self.eat("*") {
+                BinOp::Mul
+            } else if self.eat("/") {
+                BinOp::Div
+            } else {
+                return Ok(expr);
+            };
+            expr = binary_expr(op, expr, self.unary()?);
+        }
+    }
+
+    // -z^2 is -(z^2), and z^-1 is allowed
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat("-") {
+            Ok(match self.unary()? {
+                Expr::Const(value) => Expr::Const(-value),
+                arg => Expr::Neg(Box::new(arg)),
+            })
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let base = self.atom()?;
+        if self.eat("^") {
+            Ok(binary_expr(BinOp::Pow, base, self.unary()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        let token = self.next();
+        match &token.tok {
+            Tok::Num(value) => Ok(Expr::Const(Complex64::new(*value, 0.0))),
+            Tok::Imag(value) => Ok(Expr::Const(Complex64::new(0.0, *value))),
+            Tok::Op("(") => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Tok::Op("|") => {
+                let expr = self.expr()?;
+                self.expect("|")?;
+                Ok(call_expr(Func::Abs, expr))
+            }
+            Tok::Ident(name) if self.peek().tok == Tok::Op("(") => {
+                let func = match function(name) {
+                    Some(func) => func,
+                    None => return self.error(&token, format!("unknown function `{}`", name)),
+                };
+                self.pos += 1;
+                let arg = self.expr()?;
+                self.expect(")")?;
+                Ok(call_expr(func, arg))
+            }
+            Tok::Ident(name) => {
+                if let Some(var) = self.vars.iter().position(|v| v == name) {
+                    Ok(Expr::Var(var))
+                } else if let Some(value) = constant(name) {
+                    Ok(Expr::Const(value))
+                } else {
+                    self.error(&token, format!("unknown variable `{}`", name))
+                }
+            }
+            other => self.error(&token, format!("expected an expression, found {}", describe(other))),
+        }
+    }
+}
+
+// Operations on constants are done here once, so that the evaluator and the
+// bytecode both see the exponent of `z^(1 + 1)` as the constant 2
+fn binary_expr(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+    match (lhs, rhs) {
+        (Expr::Const(a), Expr::Const(b)) => Expr::Const(binary(op, a, b)),
+        (lhs, rhs) => Expr::Binary(op, Box::new(lhs), Box::new(rhs)),
+    }
+}
+
+fn call_expr(func: Func, arg: Expr) -> Expr {
+    match arg {
+        Expr::Const(value) => Expr::Const(call(func, value)),
+        arg => Expr::Call(func, Box::new(arg)),
+    }
+}
+
+fn describe(tok: &Tok) -> String {
+    match tok {
+        Tok::Num(value) => format!("`{}`", value),
+        Tok::Imag(value) => format!("`{}i`", value),
+        Tok::Ident(name) => format!("`{}`", name),
+        Tok::Op(op) => format!("`{}`", op),
+        Tok::Newline => "end of line".to_string(),
+        Tok::Eof => "end of formula".to_string(),
+    }
+}
+
+fn constant(name: &str) -> Option<Complex64> {
+    match name {
+        "i" => Some(Complex64::new(0.0, 1.0)),
+        "pi" => Some(Complex64::new(std::f64::consts::PI, 0.0)),
+        "e" => Some(Complex64::new(std::f64::consts::E, 0.0)),
+        _ => None,
+    }
+}
+
+fn function(name: &str) -> Option<Func> {
+    Some(match name {
+        "exp" => Func::Exp,
+        "log" => Func::Log,
+        "sin" => Func::Sin,
+        "cos" => Func::Cos,
+        "sinh" => Func::Sinh,
+        "cosh" => Func::Cosh,
+        "abs" => Func::Abs,
+        "arg" => Func::Arg,
+        "re" => Func::Re,
+        "im" => Func::Im,
+        "conj" => Func::Conj,
+        _ => return None,
+    })
+}
@@ -0,0 +1,234 @@
+use crate::utils::complex::Complex64;
+
+use super::{Formula, ParseError};
+
+const PIXEL: Complex64 = Complex64 {
+    real: 0.5,
+    img: -0.25,
+};
+const C: Complex64 = Complex64 {
+    real: -1.0,
+    img: 2.0,
+};
+
+// Value of `expr` with z = pixel = 0.5 - 0.25i and c = -1 + 2i
+fn value(expr: &str) -> Complex64 {
+    let formula = Formula::parse(&format!("init: v = {}\nz = z", expr))
+        .unwrap_or_else(|err| panic!("{}: {}", expr, err));
+    let var = formula.program.vars.iter().position(|v| v == "v").unwrap();
+    let mut evaluator = formula.evaluator();
+    evaluator.escape_time(PIXEL, C, 0);
+    evaluator.vars[var]
+}
+
+fn assert_value(expr: &str, real: f64, img: f64) {
+    let v = value(expr);
+    assert!(
+        (v.real - real).abs() < 1e-12 && (v.img - img).abs() < 1e-12,
+        "{} = {:?}, expected {} + {}i",
+        expr,
+        v,
+        real,
+        img
+    );
+}
+
+fn error(src: &str) -> ParseError {
+    match Formula::parse(src) {
+        Ok(_) => panic!("{:?} parsed", src),
+        Err(err) => err,
+    }
+}
+
+fn assert_error(src: &str, line: usize, column: usize, message: &str) {
+    let err = error(src);
+    assert_eq!((err.line, err.column), (line, column), "{:?}: {}", src, err);
+    assert!(
+        err.message.contains(message),
+        "{:?}: {}, expected {:?}",
+        src,
+        err,
+        message
+    );
+}
+
+#[test]
+fn precedence() {
+    assert_value("1 + 2 * 3", 7.0, 0.0);
+    assert_value("(1 + 2) * 3", 9.0, 0.0);
+    assert_value("2 * 3 ^ 2", 18.0, 0.0);
+    assert_value("1 - 2 - 3", -4.0, 0.0);
+    assert_value("8 / 2 / 2", 2.0, 0.0);
+    assert_value("1 + 2i * 2i", -3.0, 0.0);
+}
+
+#[test]
+fn unary_minus() {
+    assert_value("-2 ^ 2", -4.0, 0.0);
+    assert_value("--3", 3.0, 0.0);
+    assert_value("2 * -3", -6.0, 0.0);
+    assert_value("-z", -0.5, 0.25);
+}
+
+#[test]
+fn power_is_right_associative() {
+    assert_value("2 ^ 3 ^ 2", 512.0, 0.0);
+    assert_value("(2 ^ 3) ^ 2", 64.0, 0.0);
+    assert_value("2 ^ -1", 0.5, 0.0);
+    assert_value("2 ^ -2 ^ 2", 0.0625, 0.0);
+    assert_value("z ^ 0", 1.0, 0.0);
+}
+
+#[test]
+fn numbers() {
+    assert_value("2e1", 20.0, 0.0);
+    assert_value("1.5e-1i", 0.0, 0.15);
+    assert_value("2E+2", 200.0, 0.0);
+    assert_value("2 * e", 2.0 * std::f64::consts::E, 0.0);
+}
+
+#[test]
+fn absolute_values() {
+    let abs = PIXEL.abs();
+    assert_value("|3 - 4i|", 5.0, 0.0);
+    assert_value("||z| - 1|", 1.0 - abs, 0.0);
+    assert_value("| |z| - 1 |", 1.0 - abs, 0.0);
+    assert_value("|1 - |z||", 1.0 - abs, 0.0);
+    assert_value("|||z| - 1| - 1|", abs, 0.0);
+}
+
+#[test]
+fn conditions() {
+    // Stops at the first iteration where either bound is crossed
+    let formula = Formula::parse("z = z + 1\nbailout: re(z) < 3 || im(z) > 0").unwrap();
+    assert_eq!(formula.evaluator().escape_time(PIXEL, C, 100), 3);
+    let formula = Formula::parse("z = z + 1\nbailout: re(z) < 3 && im(z) > 0").unwrap();
+    assert_eq!(formula.evaluator().escape_time(PIXEL, C, 100), 0);
+    let formula = Formula::parse("z = z + 1\nbailout: ||z| - 1| < 2").unwrap();
+    assert_eq!(formula.evaluator().escape_time(PIXEL, C, 100), 3);
+}
+
+#[test]
+fn variables_read_zero_before_assignment() {
+    // w counts the iterations, read before it is first assigned
+    let formula = Formula::parse("z = w\nw = w + 1\nbailout: re(w) < 5").unwrap();
+    assert_eq!(formula.evaluator().escape_time(PIXEL, C, 100), 5);
+    let formula = Formula::parse("init: v = u, u = 3\nz = z").unwrap();
+    let v = formula.program.vars.iter().position(|v| v == "v").unwrap();
+    let mut evaluator = formula.evaluator();
+    evaluator.escape_time(PIXEL, C, 0);
+    assert_eq!((evaluator.vars[v].real, evaluator.vars[v].img), (0.0, 0.0));
+}
+
+#[test]
+fn error_positions() {
+    assert_error("z = z +", 1, 8, "expected an expression");
+    assert_error("z = foo(z)", 1, 5, "unknown function `foo`");
+    assert_error("z = q + 1", 1, 5, "unknown variable `q`");
+    assert_error("z = z^2 + c\nz = (z", 2, 7, "expected `)`");
+    assert_error("z = z\n  c = 1", 2, 3, "`c` cannot be assigned");
+    assert_error("z = z\npi = 1", 2, 1, "`pi` cannot be assigned");
+    assert_error(
+        "z = z\nbailout: |z| < 2 | | re(z) > 0",
+        2,
+        18,
+        "expected end of statement",
+    );
+    assert_error("z = z\nbailout: |z|", 2, 13, "expected a comparison");
+    assert_error(
+        "z = z\nbailout: |z| < 2\nbailout: |z| < 3",
+        3,
+        1,
+        "already given",
+    );
+    assert_error("loop: z = z", 1, 1, "unknown clause `loop`");
+    assert_error("init: w = 1", 1, 12, "no iteration statement");
+    assert_error("z = z $ 1", 1, 7, "unexpected character `$`");
+    assert_error("z = 1..2", 1, 5, "invalid number `1..2`");
+}
+
+// Without an operator between them `2e` is the number 2 and the constant e
+#[test]
+fn no_implicit_multiplication() {
+    assert_error("z = 2e", 1, 6, "expected end of statement, found `e`");
+    assert_error("z = 2z", 1, 6, "expected end of statement, found `z`");
+    assert_error("z = |z||z|", 1, 8, "expected end of statement");
+}
+
+// The bytecode against the tree walker, pixel by pixel over a frame of the
+// Mandelbrot set. Formulas of only arithmetic and constant whole powers
+// round the same in both and have to count the same. Other exponents, even
+// whole ones computed at run time, go through `powc`, and the vector
+// transcendental functions are a few ulps off the scalar ones, they may
+// move the counts of a few pixels on the boundary.
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+#[test]
+fn vm_agrees_with_evaluator() {
+    use super::vm::Vm;
+    use crate::utils::complex_avx::Complex64x4;
+    use core::arch::x86_64;
+
+    if !is_x86_feature_detected!("avx2") {
+        return;
+    }
+    let formulas = [
+        ("z = z^2 + c", 0.0),
+        ("z = z^5 - z^(1 + 2) + c", 0.0),
+        ("z = z^-2 + c\nbailout: |z| < 100", 0.0),
+        ("w = z\nz = w * z + c\nbailout: re(z * conj(z)) < 16", 0.0),
+        ("z = z * z + c\nbailout: re(z) < 2 && |im(z)| < 2 || re(z) < -2", 0.0),
+        ("z = z^c + pixel\nbailout: |z| < 4", 0.01),
+        ("w = w + 1\nz = z^w / w + c\nbailout: |z| < 4", 0.01),
+        ("z = exp(z) + c\nbailout: re(z) < 50", 0.01),
+        ("z = sin(z) * c\nbailout: |im(z)| < 50", 0.01),
+        ("z = cosh(z) + log(c)\nbailout: |z| < 50", 0.01),
+    ];
+    for (src, share) in formulas {
+        let formula = Formula::parse(src).unwrap();
+        let mut evaluator = formula.evaluator();
+        let (mut pixels, mut differ) = (0, 0);
+        for y in 0..48 {
+            let py = y as f64 / 20.0 - 1.2;
+            for x in (0..64).step_by(4) {
+                let px = [0, 1, 2, 3].map(|k| (x + k) as f64 / 20.0 - 2.0);
+                let mut counts = [0i64; 4];
+                unsafe {
+                    let mut vm = Vm::<Complex64x4>::new(formula.bytecode());
+                    let pixel = Complex64x4::new(
+                        x86_64::_mm256_loadu_pd(px.as_ptr()),
+                        x86_64::_mm256_set1_pd(py),
+                    );
+                    vm.start(pixel, pixel);
+                    let mut active = x86_64::_mm256_castsi256_pd(x86_64::_mm256_set1_epi64x(-1));
+                    let mut iterations = x86_64::_mm256_setzero_si256();
+                    for _ in 0..200 {
+                        active = x86_64::_mm256_and_pd(active, vm.bailout());
+                        iterations = x86_64::_mm256_sub_epi64(
+                            iterations,
+                            x86_64::_mm256_castpd_si256(active),
+                        );
+                        vm.step();
+                    }
+                    x86_64::_mm256_storeu_si256(
+                        counts.as_mut_ptr() as *mut x86_64::__m256i,
+                        iterations,
+                    );
+                }
+                for (px, count) in px.into_iter().zip(counts) {
+                    let pixel = Complex64::new(px, py);
+                    pixels += 1;
+                    if evaluator.escape_time(pixel, pixel, 200) as i64 != count {
+                        differ += 1;
+                    }
+                }
+            }
+        }
+        assert!(
+            differ as f64 <= share * pixels as f64,
+            "{:?}: {} of {} pixels count differently",
+            src,
+            differ,
+            pixels
+        );
+    }
+}
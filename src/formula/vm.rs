@@ -0,0 +1,407 @@
+use crate::utils::complex::Complex64;
+use crate::utils::complex_avx::{Complex32x8, Complex64x4};
+use core::arch::x86_64;
+
+use super::{binary, call, whole_power, BinOp, CmpOp, Cond, Expr, Func, Program, C, PIXEL, Z};
+
+// Formulas compile to straight-line code over a flat file of complex
+// registers: the program variables come first, in `Program::vars` order,
+// followed by the constants, loaded once, and one temporary per operation.
+// Bailout comparisons write to a separate file of lane masks. Nothing is
+// ever reused, formulas are short enough for the files to stay in L1.
+
+/// One instruction, operands are register numbers (masks for `Cmp`,
+/// `And` and `Or` results and the `And` and `Or` operands)
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    Mov(usize, usize),
+    Neg(usize, usize),
+    Binary(BinOp, usize, usize, usize),
+    Call(Func, usize, usize),
+    Cmp(CmpOp, usize, usize, usize),
+    And(usize, usize, usize),
+    Or(usize, usize, usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct Bytecode {
+    pub vars: usize,
+    pub registers: usize,
+    pub masks: usize,
+    pub constants: Vec<(usize, Complex64)>,
+    pub init: Vec<Op>,
+    pub step: Vec<Op>,
+    pub bailout: Vec<Op>,
+    /// Mask holding the bailout once `bailout` has run
+    pub cond: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Const(Complex64),
+    Reg(usize),
+}
+
+struct Compiler {
+    vars: usize,
+    registers: usize,
+    masks: usize,
+    constants: Vec<(usize, Complex64)>,
+}
+
+pub fn compile(program: &Program) -> Bytecode {
+    let mut compiler = Compiler {
+        vars: program.vars.len(),
+        registers: program.vars.len(),
+        masks: 0,
+        constants: Vec::new(),
+    };
+    let init = compiler.block(&program.init);
+    let step = compiler.block(&program.step);
+    let mut bailout = Vec::new();
+    let cond = compiler.cond(&program.bailout, &mut bailout);
+    Bytecode {
+        vars: compiler.vars,
+        registers: compiler.registers,
+        masks: compiler.masks,
+        constants: compiler.constants,
+        init,
+        step,
+        bailout,
+        cond,
+    }
+}
+
+impl Compiler {
+    fn temp(&mut self) -> usize {
+        self.registers += 1;
+        self.registers - 1
+    }
+
+    fn reg(&mut self, operand: Operand) -> usize {
+        match operand {
+            Operand::Reg(reg) => reg,
+            Operand::Const(value) => {
+                let found = self.constants.iter().find(|(_, c)| {
+                    c.real.to_bits() == value.real.to_bits() && c.img.to_bits() == value.img.to_bits()
+                });
+                match found {
+                    Some((reg, _)) => *reg,
+                    None => {
+                        let reg = self.temp();
+                        self.constants.push((reg, value));
+                        reg
+                    }
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, assigns: &[super::Assign]) -> Vec<Op> {
+        let mut ops = Vec::new();
+        for assign in assigns {
+            let value = self.expr(&assign.expr, &mut ops);
+            match (value, ops.last_mut()) {
+                // Retarget the op that produced the value instead of copying it
+                (Operand::Reg(reg), Some(last)) if reg >= self.vars && dst(last) == Some(reg) => {
+                    set_dst(last, assign.var)
+                }
+                _ => {
+                    let src = self.reg(value);
+                    ops.push(Op::Mov(assign.var, src));
+                }
+            }
+        }
+        ops
+    }
+
+    fn expr(&mut self, expr: &Expr, ops: &mut Vec<Op>) -> Operand {
+        match expr {
+            Expr::Const(value) => Operand::Const(*value),
+            Expr::Var(var) => Operand::Reg(*var),
+            Expr::Neg(arg) => match self.expr(arg, ops) {
+                Operand::Const(value) => Operand::Const(-value),
+                Operand::Reg(src) => {
+                    let dst = self.temp();
+                    ops.push(Op::Neg(dst, src));
+                    Operand::Reg(dst)
+                }
+            },
+            Expr::Call(func, arg) => match self.expr(arg, ops) {
+                Operand::Const(value) => Operand::Const(call(*func, value)),
+                Operand::Reg(src) => {
+                    let dst = self.temp();
+                    ops.push(Op::Call(*func, dst, src));
+                    Operand::Reg(dst)
+                }
+            },
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.expr(lhs, ops);
+                let rhs = self.expr(rhs, ops);
+                match (op, lhs, rhs) {
+                    (_, Operand::Const(a), Operand::Const(b)) => Operand::Const(binary(*op, a, b)),
+                    (BinOp::Pow, Operand::Reg(base), Operand::Const(w)) => match whole_power(w) {
+                        Some(n) => self.whole_power(base, n, ops),
+                        None => self.binary(*op, lhs, rhs, ops),
+                    },
+                    _ => self.binary(*op, lhs, rhs, ops),
+                }
+            }
+        }
+    }
+
+    fn binary(&mut self, op: BinOp, lhs: Operand, rhs: Operand, ops: &mut Vec<Op>) -> Operand {
+        let (a, b) = (self.reg(lhs), self.reg(rhs));
+        let dst = self.temp();
+        ops.push(Op::Binary(op, dst, a, b));
+        Operand::Reg(dst)
+    }
+
+    // Square and multiply, z^-n as 1 / z^n
+    fn whole_power(&mut self, base: usize, n: i64, ops: &mut Vec<Op>) -> Operand {
+        if n == 0 {
+            return Operand::Const(Complex64::new(1.0, 0.0));
+        }
+        let mut bits = n.unsigned_abs();
+        let mut square = base;
+        let mut acc = None;
+        loop {
+            if bits & 1 == 1 {
+                acc = Some(match acc {
+                    None => square,
+                    Some(acc) => {
+                        let dst = self.temp();
+                        ops.push(Op::Binary(BinOp::Mul, dst, acc, square));
+                        dst
+                    }
+                });
+            }
+            bits >>= 1;
+            if bits == 0 {
+                break;
+            }
+            let dst = self.temp();
+            ops.push(Op::Binary(BinOp::Mul, dst, square, square));
+            square = dst;
+        }
+        let acc = Operand::Reg(acc.unwrap_or(base));
+        if n > 0 {
+            acc
+        } else {
+            self.binary(BinOp::Div, Operand::Const(Complex64::new(1.0, 0.0)), acc, ops)
+        }
+    }
+
+    fn cond(&mut self, cond: &Cond, ops: &mut Vec<Op>) -> usize {
+        let (op, a, b) = match cond {
+            Cond::Cmp(op, lhs, rhs) => {
+                let lhs = self.expr(lhs, ops);
+                let rhs = self.expr(rhs, ops);
+                let (a, b) = (self.reg(lhs), self.reg(rhs));
+                self.masks += 1;
+                ops.push(Op::Cmp(*op, self.masks - 1, a, b));
+                return self.masks - 1;
+            }
+            Cond::And(lhs, rhs) => (true, lhs, rhs),
+            Cond::Or(lhs, rhs) => (false, lhs, rhs),
+        };
+        let a = self.cond(a, ops);
+        let b = self.cond(b, ops);
+        self.masks += 1;
+        let dst = self.masks - 1;
+        ops.push(if op {
+            Op::And(dst, a, b)
+        } else {
+            Op::Or(dst, a, b)
+        });
+        dst
+    }
+}
+
+fn dst(op: &Op) -> Option<usize> {
+    match *op {
+        Op::Mov(dst, _) | Op::Neg(dst, _) | Op::Binary(_, dst, ..) | Op::Call(_, dst, _) => {
+            Some(dst)
+        }
+        Op::Cmp(..) | Op::And(..) | Op::Or(..) => None,
+    }
+}
+
+fn set_dst(op: &mut Op, reg: usize) {
+    match op {
+        Op::Mov(dst, _) | Op::Neg(dst, _) | Op::Binary(_, dst, ..) | Op::Call(_, dst, _) => {
+            *dst = reg
+        }
+        Op::Cmp(..) | Op::And(..) | Op::Or(..) => {}
+    }
+}
+
+/// Complex numbers packed in vector lanes, as the VM sees them
+pub trait Lanes: Copy {
+    type Mask: Copy;
+
+    unsafe fn splat(value: Complex64) -> Self;
+    unsafe fn negate(self) -> Self;
+    unsafe fn binary(op: BinOp, a: Self, b: Self) -> Self;
+    unsafe fn call(func: Func, a: Self) -> Self;
+    /// Compares the real parts
+    unsafe fn compare(op: CmpOp, a: Self, b: Self) -> Self::Mask;
+    unsafe fn and(a: Self::Mask, b: Self::Mask) -> Self::Mask;
+    unsafe fn or(a: Self::Mask, b: Self::Mask) -> Self::Mask;
+}
+
+/// Runs a `Bytecode` over `T::LANES` pixels at a time
+pub struct Vm<'a, T: Lanes> {
+    code: &'a Bytecode,
+    regs: Vec<T>,
+    masks: Vec<T::Mask>,
+}
+
+impl<'a, T: Lanes> Vm<'a, T> {
+    pub unsafe fn new(code: &'a Bytecode) -> Self {
+        let zero = T::splat(Complex64::new(0.0, 0.0));
+        let mut regs = vec![zero; code.registers];
+        for (reg, value) in &code.constants {
+            regs[*reg] = T::splat(*value);
+        }
+        let none = T::compare(CmpOp::Lt, zero, zero);
+        Vm {
+            code,
+            regs,
+            masks: vec![none; code.masks],
+        }
+    }
+
+    /// Resets the variables for new pixels and runs `init`
+    #[inline(always)]
+    pub unsafe fn start(&mut self, pixel: T, c: T) {
+        let zero = T::splat(Complex64::new(0.0, 0.0));
+        for reg in &mut self.regs[..self.code.vars] {
+            *reg = zero;
+        }
+        self.regs[Z] = pixel;
+        self.regs[C] = c;
+        self.regs[PIXEL] = pixel;
+        let code = self.code;
+        self.run(&code.init);
+    }
+
+    /// Lanes where the bailout still holds
+    #[inline(always)]
+    pub unsafe fn bailout(&mut self) -> T::Mask {
+        let code = self.code;
+        self.run(&code.bailout);
+        self.masks[code.cond]
+    }
+
+    #[inline(always)]
+    pub unsafe fn step(&mut self) {
+        let code = self.code;
+        self.run(&code.step);
+    }
+
+    #[inline(always)]
+    unsafe fn run(&mut self, ops: &[Op]) {
+        for op in ops {
+            match *op {
+                Op::Mov(dst, src) => self.regs[dst] = self.regs[src],
+                Op::Neg(dst, src) => self.regs[dst] = self.regs[src].negate(),
+                Op::Binary(op, dst, a, b) => {
+                    self.regs[dst] = T::binary(op, self.regs[a], self.regs[b])
+                }
+                Op::Call(func, dst, src) => self.regs[dst] = T::call(func, self.regs[src]),
+                Op::Cmp(op, dst, a, b) => {
+                    self.masks[dst] = T::compare(op, self.regs[a], self.regs[b])
+                }
+                Op::And(dst, a, b) => self.masks[dst] = T::and(self.masks[a], self.masks[b]),
+                Op::Or(dst, a, b) => self.masks[dst] = T::or(self.masks[a], self.masks[b]),
+            }
+        }
+    }
+}
+
+macro_rules! impl_lanes_for {
+    ($class:ident, $mask:ty, $scalar:ty, $cmp:ident, $and:ident, $or:ident, $setzero:ident) => {
+        impl Lanes for $class {
+            type Mask = $mask;
+
+            #[inline(always)]
+            unsafe fn splat(value: Complex64) -> Self {
+                $class::splat(value.real as $scalar, value.img as $scalar)
+            }
+
+            #[inline(always)]
+            unsafe fn negate(self) -> Self {
+                self.neg()
+            }
+
+            #[inline(always)]
+            unsafe fn binary(op: BinOp, a: Self, b: Self) -> Self {
+                match op {
+                    BinOp::Add => a.add(b),
+                    BinOp::Sub => a.sub(b),
+                    BinOp::Mul => a.cmul(b),
+                    BinOp::Div => a.cdiv(b),
+                    BinOp::Pow => a.powc(b),
+                }
+            }
+
+            #[inline(always)]
+            unsafe fn call(func: Func, a: Self) -> Self {
+                let zero = x86_64::$setzero();
+                match func {
+                    Func::Exp => a.exp(),
+                    Func::Log => a.log(),
+                    Func::Sin => a.sin(),
+                    Func::Cos => a.cos(),
+                    Func::Sinh => a.sinh(),
+                    Func::Cosh => a.cosh(),
+                    Func::Abs => $class::new(a.abs(), zero),
+                    Func::Arg => $class::new(a.arg(), zero),
+                    Func::Re => $class::new(a.real, zero),
+                    Func::Im => $class::new(a.img, zero),
+                    Func::Conj => a.conjugate(),
+                }
+            }
+
+            #[inline(always)]
+            unsafe fn compare(op: CmpOp, a: Self, b: Self) -> $mask {
+                match op {
+                    CmpOp::Lt => x86_64::$cmp::<{ x86_64::_CMP_LT_OQ }>(a.real, b.real),
+                    CmpOp::Le => x86_64::$cmp::<{ x86_64::_CMP_LE_OQ }>(a.real, b.real),
+                    CmpOp::Gt => x86_64::$cmp::<{ x86_64::_CMP_GT_OQ }>(a.real, b.real),
+                    CmpOp::Ge => x86_64::$cmp::<{ x86_64::_CMP_GE_OQ }>(a.real, b.real),
+                }
+            }
+
+            #[inline(always)]
+            unsafe fn and(a: $mask, b: $mask) -> $mask {
+                x86_64::$and(a, b)
+            }
+
+            #[inline(always)]
+            unsafe fn or(a: $mask, b: $mask) -> $mask {
+                x86_64::$or(a, b)
+            }
+        }
+    };
+}
+
+impl_lanes_for!(
+    Complex64x4,
+    x86_64::__m256d,
+    f64,
+    _mm256_cmp_pd,
+    _mm256_and_pd,
+    _mm256_or_pd,
+    _mm256_setzero_pd
+);
+impl_lanes_for!(
+    Complex32x8,
+    x86_64::__m256,
+    f32,
+    _mm256_cmp_ps,
+    _mm256_and_ps,
+    _mm256_or_ps,
+    _mm256_setzero_ps
+);
@@ -0,0 +1,265 @@
+use crate::utils::complex::{Complex32, Complex64};
+
+use crate::fractal::{Block, IterationType};
+
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+use super::lanes::{self, Complex, Kernel, Lanes, Real};
+use super::{AbsVariant, FSignature, InstructionSet, Precision, RenderContext};
+
+// Each variant of the family is a combination of these folds, applied
+// around the `z^pow` step:
+//
+//  ABS_X  / ABS_Y: take |Re z| / |Im z| before raising to the power,
+//  ABS_RE        : take |Re z^pow| after raising to the power,
+//  NEG_IM        : conjugate the result (negate Im z^pow).
+const ABS_X: u8 = 1;
+const ABS_Y: u8 = 2;
+const ABS_RE: u8 = 4;
+const NEG_IM: u8 = 8;
+
+const TRICORN: u8 = NEG_IM;
+const CELTIC: u8 = ABS_RE;
+const CELTIC_MANDELBAR: u8 = ABS_RE | NEG_IM;
+const BUFFALO: u8 = ABS_RE | ABS_X | ABS_Y | NEG_IM;
+const PERPENDICULAR_MANDELBROT: u8 = ABS_X | NEG_IM;
+const PERPENDICULAR_BURNING_SHIP: u8 = ABS_Y | NEG_IM;
+const PERPENDICULAR_CELTIC: u8 = ABS_RE | ABS_X | NEG_IM;
+const HEART: u8 = ABS_X;
+
+pub fn fn_(variant: AbsVariant, julia: bool, prec: Precision, ins: InstructionSet) -> FSignature {
+    match variant {
+        AbsVariant::Tricorn => kernel::<TRICORN>(julia, prec, ins),
+        AbsVariant::Celtic => kernel::<CELTIC>(julia, prec, ins),
+        AbsVariant::CelticMandelbar => kernel::<CELTIC_MANDELBAR>(julia, prec, ins),
+        AbsVariant::Buffalo => kernel::<BUFFALO>(julia, prec, ins),
+        AbsVariant::PerpendicularMandelbrot => kernel::<PERPENDICULAR_MANDELBROT>(julia, prec, ins),
+        AbsVariant::PerpendicularBurningShip => {
+            kernel::<PERPENDICULAR_BURNING_SHIP>(julia, prec, ins)
+        }
+        AbsVariant::PerpendicularCeltic => kernel::<PERPENDICULAR_CELTIC>(julia, prec, ins),
+        AbsVariant::Heart => kernel::<HEART>(julia, prec, ins),
+    }
+}
+
+// With `J` set the pixel is the starting z and the seed is the constant,
+// otherwise the pixel is both, as in the parameter plane.
+fn kernel<const V: u8>(julia: bool, prec: Precision, ins: InstructionSet) -> FSignature {
+    if julia {
+        kernel_for::<V, true>(prec, ins)
+    } else {
+        kernel_for::<V, false>(prec, ins)
+    }
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "simd-portable",
+        all(target_arch = "x86_64", feature = "simd-x86")
+    )),
+    allow(unused_variables)
+)]
+fn kernel_for<const V: u8, const J: bool>(prec: Precision, ins: InstructionSet) -> FSignature {
+    #[cfg(any(
+        feature = "simd-portable",
+        all(target_arch = "x86_64", feature = "simd-x86")
+    ))]
+    if let Some(kernel) = lanes::kernel::<AbsVariation<V, J>>(prec, ins) {
+        return kernel;
+    }
+    match prec {
+        Precision::F64 | Precision::Fixed => abs_variation::<V, J>,
+        Precision::F32 => abs_variationf32::<V, J>,
+    }
+}
+
+#[inline(always)]
+fn pixel<const V: u8>(
+    mut z: Complex64,
+    a: Complex64,
+    pow: u32,
+    max_iterations: u32,
+) -> IterationType {
+    let mut iterations = 0;
+    while iterations < max_iterations && z.abs_sq() < 4.0 {
+        if V & ABS_X != 0 {
+            z.real = z.real.abs();
+        }
+        if V & ABS_Y != 0 {
+            z.img = z.img.abs();
+        }
+        z = z.ipow(pow);
+        if V & ABS_RE != 0 {
+            z.real = z.real.abs();
+        }
+        if V & NEG_IM != 0 {
+            z.img = -z.img;
+        }
+        z += a;
+        iterations += 1;
+    }
+    iterations as IterationType
+}
+
+#[inline(always)]
+fn pixelf32<const V: u8>(
+    mut z: Complex32,
+    a: Complex32,
+    pow: u32,
+    max_iterations: u32,
+) -> IterationType {
+    let mut iterations = 0;
+    while iterations < max_iterations && z.abs_sq() < 4.0 {
+        if V & ABS_X != 0 {
+            z.real = z.real.abs();
+        }
+        if V & ABS_Y != 0 {
+            z.img = z.img.abs();
+        }
+        z = z.ipow(pow);
+        if V & ABS_RE != 0 {
+            z.real = z.real.abs();
+        }
+        if V & NEG_IM != 0 {
+            z.img = -z.img;
+        }
+        z += a;
+        iterations += 1;
+    }
+    iterations as IterationType
+}
+
+pub fn abs_variation<const V: u8, const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        pow,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let h = height as f64;
+    let seed = params.seed;
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f64 + params.offset.1;
+        for xcoord in 0..width {
+            let x = xcoord as f64 + params.offset.0;
+            let z = Complex64::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let a = if J { seed } else { z };
+            block[ycoord - hstart][xcoord] = pixel::<V>(z, a, pow, max_iterations);
+        }
+    }
+    (hstart, hend)
+}
+
+pub fn abs_variationf32<const V: u8, const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        pow,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let h = height as f32;
+    let seed = Complex32::new(params.seed.real as f32, params.seed.img as f32);
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f32 + params.offset.1 as f32;
+        for xcoord in 0..width {
+            let x = xcoord as f32 + params.offset.0 as f32;
+            let z = Complex32::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let a = if J { seed } else { z };
+            block[ycoord - hstart][xcoord] = pixelf32::<V>(z, a, pow, max_iterations);
+        }
+    }
+    (hstart, hend)
+}
+
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+struct AbsVariation<const V: u8, const J: bool>;
+
+// The scalar kernels over lanes, folding and counting in the same order
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+impl<const V: u8, const J: bool> Kernel for AbsVariation<V, J> {
+    #[inline(always)]
+    unsafe fn render<L: Lanes>(ctx: RenderContext) -> Block {
+        let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+        let RenderContext {
+            width,
+            height,
+            max_iter: max_iterations,
+            pow,
+            mut block,
+            params,
+            ..
+        } = ctx;
+        let four = L::splat(L::Real::from_f64(4.0));
+        let seed = Complex::<L>::splat(
+            L::Real::from_f64(params.seed.real),
+            L::Real::from_f64(params.seed.img),
+        );
+
+        for ycoord in hstart..hend {
+            if params.cancelled() {
+                return (hstart, ycoord);
+            }
+            for xcoord in (0..width).step_by(L::LANES) {
+                let lanes = L::LANES.min(width - xcoord);
+                let mut z =
+                    Complex::<L>::pixels(xcoord, ycoord, height, params.offset, (-1.8, -1.0));
+                let a = if J { seed } else { z };
+                let mut active = L::first(lanes);
+                let mut iterations = L::counter(0);
+
+                for _ in 0..max_iterations {
+                    active = L::and(active, z.abs_sq().lt(four));
+                    if !L::any(active) {
+                        break;
+                    }
+                    iterations = L::count(iterations, active);
+
+                    if V & ABS_X != 0 {
+                        z.real = z.real.abs();
+                    }
+                    if V & ABS_Y != 0 {
+                        z.img = z.img.abs();
+                    }
+                    z = z.ipow(pow);
+                    if V & ABS_RE != 0 {
+                        z.real = z.real.abs();
+                    }
+                    if V & NEG_IM != 0 {
+                        z.img = z.img.neg();
+                    }
+                    z = z.add(a);
+                }
+
+                L::store(
+                    iterations,
+                    &mut block[ycoord - hstart][xcoord..xcoord + lanes],
+                );
+            }
+        }
+        (hstart, hend)
+    }
+}
@@ -0,0 +1,126 @@
+use crate::utils::color::{from_linear, to_linear};
+use crate::utils::image::Color;
+
+use super::{paint, Antialias, FrameBuffer, RenderContext, Scene};
+
+// Pixels are resampled on a grid centred on their first sample, rendered
+// CHUNK pixels at a time by shifting the kernel through `Params::offset`.
+// CHUNK is a multiple of every lane count, up to the 16 f32 lanes of
+// AVX-512, so the vector kernels never run their tails, and chunks near
+// the right edge render past it. The cells of
+// the grid are jittered by a hash of the row, chunk and cell: all pixels of
+// a chunk share one pattern, which keeps a chunk to one kernel call per
+// sample.
+const CHUNK: usize = 16;
+
+/// Resample the painted rows `image`, which start at `first_row` of the
+/// frame. `painted` is the whole frame before refinement, the adaptive mode
+/// compares neighbours in it.
+pub fn refine(image: &mut [Color], first_row: usize, painted: &[Color], scene: &Scene) {
+    let Scene {
+        width,
+        height,
+        max_iter,
+        pow,
+        func,
+        params,
+        palette,
+        mode,
+        antialias,
+    } = *scene;
+    let (grid, threshold) = match antialias {
+        Antialias::Off => return,
+        Antialias::Supersample(grid) => (grid.max(1) as usize, None),
+        Antialias::Adaptive(grid, threshold) => (grid.max(1) as usize, Some(threshold)),
+    };
+    let samples = (grid * grid) as f32;
+    let mut block = FrameBuffer::new(CHUNK, 1);
+    let mut values = FrameBuffer::new(CHUNK, 1);
+    let mut params = params.clone();
+
+    for row in first_row..first_row + image.len() / width {
+        for x0 in (0..width).step_by(CHUNK) {
+            let mut flagged = [false; CHUNK];
+            for (x, flag) in (x0..width.min(x0 + CHUNK)).zip(flagged.iter_mut()) {
+                *flag = match threshold {
+                    None => true,
+                    Some(threshold) => edge(painted, width, height, row, x, threshold),
+                };
+            }
+            if !flagged.contains(&true) {
+                continue;
+            }
+
+            let mut sums = [[0.0; 3]; CHUNK];
+            for cell in 0..grid * grid {
+                let (i, j) = ((cell % grid) as f64, (cell / grid) as f64);
+                let jx = jitter(row, x0, 2 * cell);
+                let jy = jitter(row, x0, 2 * cell + 1);
+                params.offset = (
+                    x0 as f64 + (i + jx) / grid as f64 - 0.5,
+                    (j + jy) / grid as f64 - 0.5,
+                );
+                func.render(RenderContext {
+                    rows: row..row + 1,
+                    width: CHUNK,
+                    height,
+                    max_iter: max_iter as u32,
+                    pow,
+                    block: block.view(),
+                    values: values.view(),
+                    params: &params,
+                });
+                for (k, sum) in sums.iter_mut().enumerate() {
+                    if flagged[k] {
+                        let color = paint(palette, mode, block[0][k], values[0][k]);
+                        sum[0] += to_linear(color.r);
+                        sum[1] += to_linear(color.g);
+                        sum[2] += to_linear(color.b);
+                    }
+                }
+            }
+            for (k, sum) in sums.iter().enumerate() {
+                if flagged[k] {
+                    image[(row - first_row) * width + x0 + k] = Color {
+                        r: from_linear(sum[0] / samples),
+                        g: from_linear(sum[1] / samples),
+                        b: from_linear(sum[2] / samples),
+                    };
+                }
+            }
+        }
+    }
+}
+
+// Whether a pixel differs from one of its four neighbours by more than
+// `threshold` in some channel
+fn edge(
+    painted: &[Color],
+    width: usize,
+    height: usize,
+    row: usize,
+    x: usize,
+    threshold: u8,
+) -> bool {
+    let color = painted[row * width + x];
+    let differs = |other: Color| {
+        color.r.abs_diff(other.r) > threshold
+            || color.g.abs_diff(other.g) > threshold
+            || color.b.abs_diff(other.b) > threshold
+    };
+    (row > 0 && differs(painted[(row - 1) * width + x]))
+        || (row + 1 < height && differs(painted[(row + 1) * width + x]))
+        || (x > 0 && differs(painted[row * width + x - 1]))
+        || (x + 1 < width && differs(painted[row * width + x + 1]))
+}
+
+// Deterministic jitter in [0, 1)
+fn jitter(row: usize, x: usize, n: usize) -> f64 {
+    let mut h = (row as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (x as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+        ^ (n as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+    h ^= h >> 29;
+    h = h.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h ^= h >> 32;
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,414 @@
+use crate::utils::complex::{Complex32, Complex64};
+use std::f64::consts::PI;
+
+use crate::fractal::{Block, IterationType};
+
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+use super::lanes::{self, sin_cos, Complex, Kernel, Lanes, Real, MAX_LANES};
+use super::{Average, ColorMode, FSignature, InstructionSet, Params, Precision, RenderContext};
+
+// Additive coloring: a statistic t(z_n) of every point of the orbit is
+// averaged, and the averages over the first n and n - 1 points are blended
+// with the fractional part of the smooth iteration count, which hides the
+// iteration bands. The blend goes to the value buffer, the escape iteration
+// to the iteration buffer. Bounded orbits keep the plain average.
+//
+// `B` applies the Burning Ship folds before the power, `J` takes the
+// constant from the seed.
+const STRIPE: u8 = 0;
+const TRIANGLE: u8 = 1;
+const CURVATURE: u8 = 2;
+
+// The smooth iteration count needs orbits to run well past |z| = 2
+const BAILOUT: f64 = 1000.0;
+
+pub fn fn_(
+    average: Average,
+    burning_ship: bool,
+    julia: bool,
+    prec: Precision,
+    ins: InstructionSet,
+) -> FSignature {
+    match average {
+        Average::Stripe(_) => family::<STRIPE>(burning_ship, julia, prec, ins),
+        Average::TriangleInequality => family::<TRIANGLE>(burning_ship, julia, prec, ins),
+        Average::Curvature => family::<CURVATURE>(burning_ship, julia, prec, ins),
+    }
+}
+
+fn family<const S: u8>(
+    burning_ship: bool,
+    julia: bool,
+    prec: Precision,
+    ins: InstructionSet,
+) -> FSignature {
+    match (burning_ship, julia) {
+        (false, false) => kernel::<S, false, false>(prec, ins),
+        (false, true) => kernel::<S, false, true>(prec, ins),
+        (true, false) => kernel::<S, true, false>(prec, ins),
+        (true, true) => kernel::<S, true, true>(prec, ins),
+    }
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "simd-portable",
+        all(target_arch = "x86_64", feature = "simd-x86")
+    )),
+    allow(unused_variables)
+)]
+fn kernel<const S: u8, const B: bool, const J: bool>(
+    prec: Precision,
+    ins: InstructionSet,
+) -> FSignature {
+    #[cfg(any(
+        feature = "simd-portable",
+        all(target_arch = "x86_64", feature = "simd-x86")
+    ))]
+    if let Some(kernel) = lanes::kernel::<Averaged<S, B, J>>(prec, ins) {
+        return kernel;
+    }
+    match prec {
+        Precision::F64 | Precision::Fixed => average::<S, B, J>,
+        Precision::F32 => averagef32::<S, B, J>,
+    }
+}
+
+// The Burning Ship parameter plane is framed as in `burning_ship::fn_`
+#[inline(always)]
+fn origin<const B: bool, const J: bool>() -> (f64, f64) {
+    if B && !J {
+        (-2.0, -0.5)
+    } else {
+        (-1.8, -1.0)
+    }
+}
+
+// Stripe density of the scene, None when the scene is not colored by an
+// average
+#[inline(always)]
+fn density(params: &Params) -> Option<f64> {
+    match params.color {
+        ColorMode::Average(Average::Stripe(density)) => Some(density),
+        ColorMode::Average(_) => Some(0.0),
+        _ => None,
+    }
+}
+
+// Curvature needs three points, the first iterate has no term
+#[inline(always)]
+fn interpolate<const S: u8>(
+    sum: f64,
+    previous: f64,
+    iterations: u32,
+    abs_sq: f64,
+    pow: u32,
+) -> f32 {
+    let skip = if S == CURVATURE { 1 } else { 0 };
+    let count = iterations.saturating_sub(skip);
+    if count == 0 {
+        return 0.0;
+    }
+    let average = sum / count as f64;
+    if abs_sq < BAILOUT * BAILOUT || count == 1 {
+        return average as f32;
+    }
+    let previous = previous / (count - 1) as f64;
+    let frac = 1.0 + (BAILOUT.ln() / (0.5 * abs_sq.ln())).ln() / (pow as f64).ln();
+    (frac * average + (1.0 - frac) * previous) as f32
+}
+
+#[inline(always)]
+fn pixel<const S: u8, const B: bool>(
+    mut z: Complex64,
+    c: Complex64,
+    density: f64,
+    pow: u32,
+    max_iterations: u32,
+) -> (IterationType, f32) {
+    let abs_c = c.abs_sq().sqrt();
+    let mut last = z;
+    let mut sum = 0.0;
+    let mut previous = 0.0;
+    let mut iterations = 0;
+    while iterations < max_iterations && z.abs_sq() < BAILOUT * BAILOUT {
+        let mut w = z;
+        if B {
+            w.real = -w.real.abs();
+            w.img = w.img.abs();
+        }
+        let zp = w.ipow(pow);
+        let next = zp + &c;
+        iterations += 1;
+        let t = match S {
+            STRIPE => 0.5 + 0.5 * (density * next.arg()).sin(),
+            // Magnitudes as the vector kernels take them, without `hypot`:
+            // the term is a small difference of large ones when |z^p| is
+            // far from |c|, and amplifies a rounding apart
+            TRIANGLE => {
+                let a = zp.abs_sq().sqrt();
+                let (lo, hi) = ((a - abs_c).abs(), a + abs_c);
+                if hi > lo {
+                    (next.abs_sq().sqrt() - lo) / (hi - lo)
+                } else {
+                    0.0
+                }
+            }
+            _ if iterations > 1 => ((next - &z) * (z - &last).conjugate()).arg().abs() / PI,
+            _ => 0.0,
+        };
+        previous = sum;
+        sum += t;
+        last = z;
+        z = next;
+    }
+    let value = interpolate::<S>(sum, previous, iterations, z.abs_sq(), pow);
+    (iterations as IterationType, value)
+}
+
+#[inline(always)]
+fn pixelf32<const S: u8, const B: bool>(
+    mut z: Complex32,
+    c: Complex32,
+    density: f32,
+    pow: u32,
+    max_iterations: u32,
+) -> (IterationType, f32) {
+    let abs_c = c.abs_sq().sqrt();
+    let mut last = z;
+    let mut sum = 0.0;
+    let mut previous = 0.0;
+    let mut iterations = 0;
+    while iterations < max_iterations && z.abs_sq() < (BAILOUT * BAILOUT) as f32 {
+        let mut w = z;
+        if B {
+            w.real = -w.real.abs();
+            w.img = w.img.abs();
+        }
+        let zp = w.ipow(pow);
+        let next = zp + &c;
+        iterations += 1;
+        let t = match S {
+            STRIPE => 0.5 + 0.5 * (density * next.arg()).sin(),
+            TRIANGLE => {
+                let a = zp.abs_sq().sqrt();
+                let (lo, hi) = ((a - abs_c).abs(), a + abs_c);
+                if hi > lo {
+                    (next.abs_sq().sqrt() - lo) / (hi - lo)
+                } else {
+                    0.0
+                }
+            }
+            _ if iterations > 1 => ((next - &z) * (z - &last).conjugate()).arg().abs() / PI as f32,
+            _ => 0.0,
+        };
+        previous = sum;
+        sum += t;
+        last = z;
+        z = next;
+    }
+    let value = interpolate::<S>(
+        sum as f64,
+        previous as f64,
+        iterations,
+        z.abs_sq() as f64,
+        pow,
+    );
+    (iterations as IterationType, value)
+}
+
+pub fn average<const S: u8, const B: bool, const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        pow,
+        mut block,
+        mut values,
+        params,
+        ..
+    } = ctx;
+    let density = match density(params) {
+        Some(density) => density,
+        None => return (hstart, hend),
+    };
+    let h = height as f64;
+    let (ox, oy) = origin::<B, J>();
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f64 + params.offset.1;
+        for xcoord in 0..width {
+            let x = xcoord as f64 + params.offset.0;
+            let z = Complex64::new(x / h * 2.0 + ox, y / h * 2.0 + oy);
+            let c = if J { params.seed } else { z };
+            let (iteration, value) = pixel::<S, B>(z, c, density, pow, max_iterations);
+            block[ycoord - hstart][xcoord] = iteration;
+            values[ycoord - hstart][xcoord] = value;
+        }
+    }
+    (hstart, hend)
+}
+
+pub fn averagef32<const S: u8, const B: bool, const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        pow,
+        mut block,
+        mut values,
+        params,
+        ..
+    } = ctx;
+    let density = match density(params) {
+        Some(density) => density as f32,
+        None => return (hstart, hend),
+    };
+    let h = height as f32;
+    let (ox, oy) = origin::<B, J>();
+    let (ox, oy) = (ox as f32, oy as f32);
+    let seed = Complex32::new(params.seed.real as f32, params.seed.img as f32);
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f32 + params.offset.1 as f32;
+        for xcoord in 0..width {
+            let x = xcoord as f32 + params.offset.0 as f32;
+            let z = Complex32::new(x / h * 2.0 + ox, y / h * 2.0 + oy);
+            let c = if J { seed } else { z };
+            let (iteration, value) = pixelf32::<S, B>(z, c, density, pow, max_iterations);
+            block[ycoord - hstart][xcoord] = iteration;
+            values[ycoord - hstart][xcoord] = value;
+        }
+    }
+    (hstart, hend)
+}
+
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+struct Averaged<const S: u8, const B: bool, const J: bool>;
+
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+impl<const S: u8, const B: bool, const J: bool> Kernel for Averaged<S, B, J> {
+    #[inline(always)]
+    unsafe fn render<L: Lanes>(ctx: RenderContext) -> Block {
+        let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+        let RenderContext {
+            width,
+            height,
+            max_iter: max_iterations,
+            pow,
+            mut block,
+            mut values,
+            params,
+            ..
+        } = ctx;
+        let density = match density(params) {
+            Some(density) => L::splat(L::Real::from_f64(density)),
+            None => return (hstart, hend),
+        };
+        let real = |value: f64| L::splat(L::Real::from_f64(value));
+        let (zero, half, pi) = (real(0.0), real(0.5), real(PI));
+        let bailout = real(BAILOUT * BAILOUT);
+        let seed = Complex::<L>::splat(
+            L::Real::from_f64(params.seed.real),
+            L::Real::from_f64(params.seed.img),
+        );
+        let mut counts = [0; MAX_LANES];
+        let mut sums = [L::Real::ZERO; MAX_LANES];
+        let mut previouses = [L::Real::ZERO; MAX_LANES];
+        let mut mags = [L::Real::ZERO; MAX_LANES];
+
+        for ycoord in hstart..hend {
+            if params.cancelled() {
+                return (hstart, ycoord);
+            }
+            for xcoord in (0..width).step_by(L::LANES) {
+                let lanes = L::LANES.min(width - xcoord);
+                let mut z =
+                    Complex::<L>::pixels(xcoord, ycoord, height, params.offset, origin::<B, J>());
+                let c = if J { seed } else { z };
+                let abs_c = c.abs_sq().sqrt();
+                let mut last = z;
+                let mut sum = zero;
+                let mut previous = zero;
+                let mut active = L::first(lanes);
+                let mut iterations = L::counter(0);
+
+                for i in 0..max_iterations {
+                    active = L::and(active, z.abs_sq().lt(bailout));
+                    if !L::any(active) {
+                        break;
+                    }
+                    iterations = L::count(iterations, active);
+
+                    let mut w = z;
+                    if B {
+                        w.real = w.real.abs().neg();
+                        w.img = w.img.abs();
+                    }
+                    let zp = w.ipow(pow);
+                    let next = zp.add(c);
+
+                    let t = match S {
+                        STRIPE => {
+                            let (sin, _) = sin_cos(density.mul(next.arg()));
+                            half.mul_add(sin, half)
+                        }
+                        TRIANGLE => {
+                            let a = zp.abs_sq().sqrt();
+                            let lo = a.sub(abs_c).abs();
+                            let span = a.add(abs_c).sub(lo);
+                            let t = next.abs_sq().sqrt().sub(lo).div(span);
+                            L::select(span.gt(zero), t, zero)
+                        }
+                        _ if i > 0 => {
+                            let turn = next.sub(z).cmul(z.sub(last).conjugate());
+                            turn.arg().abs().div(pi)
+                        }
+                        _ => zero,
+                    };
+
+                    // Escaped lanes keep their last point for the smooth count
+                    previous = L::select(active, sum, previous);
+                    sum = L::select(active, sum.add(t), sum);
+                    if S == CURVATURE {
+                        last = Complex::select(active, z, last);
+                    }
+                    z = Complex::select(active, next, z);
+                }
+
+                L::store(iterations, &mut counts[..lanes]);
+                sum.write(&mut sums[..lanes]);
+                previous.write(&mut previouses[..lanes]);
+                z.abs_sq().write(&mut mags[..lanes]);
+                for lane in 0..lanes {
+                    block[ycoord - hstart][xcoord + lane] = counts[lane];
+                    values[ycoord - hstart][xcoord + lane] = interpolate::<S>(
+                        sums[lane].to_f64(),
+                        previouses[lane].to_f64(),
+                        counts[lane],
+                        mags[lane].to_f64(),
+                        pow,
+                    );
+                }
+            }
+        }
+        (hstart, hend)
+    }
+}
@@ -0,0 +1,154 @@
+use std::ops::{Index, IndexMut, Range};
+
+// Samples per 32-byte line, rows start on a line so AVX loads and stores
+// of a row never straddle one
+const LINE: usize = 8;
+
+#[derive(Clone, Copy, Default)]
+#[repr(C, align(32))]
+struct Line<T>([T; LINE]);
+
+/// Per-pixel samples of a frame, row-major in one allocation with rows
+/// `stride` samples apart. Indexing gives the `width` samples of a row.
+pub struct FrameBuffer<T> {
+    lines: Vec<Line<T>>,
+    width: usize,
+    height: usize,
+    stride: usize,
+}
+
+/// Mutable view of consecutive rows of a `FrameBuffer`, indexed from the
+/// first row of the view
+pub struct Rows<'a, T> {
+    data: &'a mut [T],
+    width: usize,
+    stride: usize,
+}
+
+impl<T: Copy + Default> FrameBuffer<T> {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut buffer = FrameBuffer {
+            lines: Vec::new(),
+            width: 0,
+            height: 0,
+            stride: 0,
+        };
+        buffer.resize(width, height);
+        buffer
+    }
+
+    /// Change the dimensions, keeping the allocation when it is big enough.
+    /// The samples are left unspecified.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let lines = width.div_ceil(LINE);
+        self.lines.resize(lines * height, Line::default());
+        self.width = width;
+        self.height = height;
+        self.stride = lines * LINE;
+    }
+}
+
+impl<T> FrameBuffer<T> {
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.height).map(move |row| &self[row])
+    }
+
+    /// View of every row
+    pub fn view(&mut self) -> Rows<'_, T> {
+        let (width, stride) = (self.width, self.stride);
+        Rows {
+            data: self.data_mut(),
+            width,
+            stride,
+        }
+    }
+
+    /// Views of consecutive runs of `rows` rows, the last one may be short.
+    /// A frame without pixels has no tiles.
+    pub fn tiles(&mut self, rows: usize) -> impl Iterator<Item = Rows<'_, T>> {
+        let (width, stride) = (self.width, self.stride);
+        // A stride of 0 leaves no data to split
+        self.data_mut()
+            .chunks_mut((rows.max(1) * stride).max(1))
+            .map(move |data| Rows {
+                data,
+                width,
+                stride,
+            })
+    }
+
+    // The lines are read as `LINE` samples back to back, which holds for
+    // samples of 4 bytes, the 32 bytes of a line leave no padding then
+    const SAMPLES: () = assert!(std::mem::size_of::<T>() * LINE == 32);
+
+    fn data(&self) -> &[T] {
+        let () = Self::SAMPLES;
+        let len = self.lines.len() * LINE;
+        unsafe { std::slice::from_raw_parts(self.lines.as_ptr() as *const T, len) }
+    }
+
+    fn data_mut(&mut self) -> &mut [T] {
+        let () = Self::SAMPLES;
+        let len = self.lines.len() * LINE;
+        unsafe { std::slice::from_raw_parts_mut(self.lines.as_mut_ptr() as *mut T, len) }
+    }
+}
+
+impl<T> Index<usize> for FrameBuffer<T> {
+    type Output = [T];
+
+    #[inline(always)]
+    fn index(&self, row: usize) -> &[T] {
+        &self.data()[row * self.stride..][..self.width]
+    }
+}
+
+impl<T> Rows<'_, T> {
+    /// Number of rows
+    #[inline]
+    pub fn len(&self) -> usize {
+        match self.stride {
+            0 => 0,
+            stride => self.data.len() / stride,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.len()).map(move |row| &self[row])
+    }
+
+    /// View of the same rows for a shorter borrow
+    pub fn reborrow(&mut self) -> Rows<'_, T> {
+        self.slice(0..self.len())
+    }
+
+    /// View of some of the rows, indexed from the first of them
+    pub fn slice(&mut self, rows: Range<usize>) -> Rows<'_, T> {
+        Rows {
+            data: &mut self.data[rows.start * self.stride..rows.end * self.stride],
+            width: self.width,
+            stride: self.stride,
+        }
+    }
+}
+
+impl<T> Index<usize> for Rows<'_, T> {
+    type Output = [T];
+
+    #[inline(always)]
+    fn index(&self, row: usize) -> &[T] {
+        &self.data[row * self.stride..][..self.width]
+    }
+}
+
+impl<T> IndexMut<usize> for Rows<'_, T> {
+    #[inline(always)]
+    fn index_mut(&mut self, row: usize) -> &mut [T] {
+        &mut self.data[row * self.stride..][..self.width]
+    }
+}
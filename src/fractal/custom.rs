@@ -0,0 +1,200 @@
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+use crate::formula::vm::Vm;
+use crate::utils::complex::Complex64;
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+use crate::utils::complex_avx::{Complex32x8, Complex64x4};
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+use core::arch::x86_64;
+
+use crate::fractal::{Block, IterationType};
+
+use super::{FSignature, InstructionSet, Precision, RenderContext};
+
+// With AVX the formula runs as bytecode over a vector of pixels, otherwise
+// the tree is interpreted one pixel at a time over `Complex64`, whatever
+// the precision. There are no SSE counterparts of the vector math.
+
+pub fn fn_(julia: bool, prec: Precision, ins: InstructionSet) -> FSignature {
+    if julia {
+        kernel::<true>(prec, ins)
+    } else {
+        kernel::<false>(prec, ins)
+    }
+}
+
+fn kernel<const J: bool>(prec: Precision, ins: InstructionSet) -> FSignature {
+    match (prec, ins) {
+        #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+        (Precision::F64 | Precision::Fixed, InstructionSet::AVX | InstructionSet::AVX512) => {
+            custom_simd256::<J>
+        }
+        #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+        (Precision::F32, InstructionSet::AVX | InstructionSet::AVX512) => customf32_simd256::<J>,
+        _ => custom::<J>,
+    }
+}
+
+pub fn custom<const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let formula = match params.formula {
+        Some(ref formula) => formula,
+        None => return (hstart, hend),
+    };
+    let mut evaluator = formula.evaluator();
+    let h = height as f64;
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f64 + params.offset.1;
+        for xcoord in 0..width {
+            let x = xcoord as f64 + params.offset.0;
+            let pixel = Complex64::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let c = if J { params.seed } else { pixel };
+            block[ycoord - hstart][xcoord] =
+                evaluator.escape_time(pixel, c, max_iterations) as IterationType;
+        }
+    }
+    (hstart, hend)
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn custom_simd256<const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let formula = match params.formula {
+        Some(ref formula) => formula,
+        None => return (hstart, hend),
+    };
+    let mut vm = Vm::<Complex64x4>::new(formula.bytecode());
+    let h = height as f64;
+    let mut iter: [i64; 4] = [0; 4];
+    let lane = x86_64::_mm256_setr_epi64x(0, 1, 2, 3);
+    let seed = Complex64x4::splat(params.seed.real, params.seed.img);
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f64 + params.offset.1;
+        let py = x86_64::_mm256_set1_pd(y / h * 2.0 - 1.0);
+        // The last vector of a row is partial, its extra lanes start inactive
+        for xcoord in (0..width).step_by(4) {
+            let lanes = (width - xcoord).min(4);
+            let (x, xoff) = (xcoord as f64, params.offset.0);
+            let px = x86_64::_mm256_setr_pd(
+                (x + xoff) / h * 2.0 - 1.8,
+                (x + 1.0 + xoff) / h * 2.0 - 1.8,
+                (x + 2.0 + xoff) / h * 2.0 - 1.8,
+                (x + 3.0 + xoff) / h * 2.0 - 1.8,
+            );
+            let pixel = Complex64x4::new(px, py);
+            vm.start(pixel, if J { seed } else { pixel });
+            let mut active = x86_64::_mm256_castsi256_pd(x86_64::_mm256_cmpgt_epi64(
+                x86_64::_mm256_set1_epi64x(lanes as i64),
+                lane,
+            ));
+            let mut iterations = x86_64::_mm256_setzero_si256();
+
+            for _ in 0..max_iterations {
+                active = x86_64::_mm256_and_pd(active, vm.bailout());
+                if x86_64::_mm256_movemask_pd(active) == 0 {
+                    break;
+                }
+                iterations =
+                    x86_64::_mm256_sub_epi64(iterations, x86_64::_mm256_castpd_si256(active));
+                vm.step();
+            }
+
+            x86_64::_mm256_storeu_si256(iter.as_mut_ptr() as *mut x86_64::__m256i, iterations);
+            for (lane, count) in iter[..lanes].iter().enumerate() {
+                block[ycoord - hstart][xcoord + lane] = *count as IterationType;
+            }
+        }
+    }
+    (hstart, hend)
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn customf32_simd256<const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let formula = match params.formula {
+        Some(ref formula) => formula,
+        None => return (hstart, hend),
+    };
+    let mut vm = Vm::<Complex32x8>::new(formula.bytecode());
+    let h = height as f32;
+    let mut iter: [IterationType; 8] = [0; 8];
+    let lane = x86_64::_mm256_setr_epi32(0, 1, 2, 3, 4, 5, 6, 7);
+    let seed = Complex32x8::splat(params.seed.real as f32, params.seed.img as f32);
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f32 + params.offset.1 as f32;
+        let py = x86_64::_mm256_set1_ps(y / h * 2.0 - 1.0);
+        // The last vector of a row is partial, its extra lanes start inactive
+        for xcoord in (0..width).step_by(8) {
+            let lanes = (width - xcoord).min(8);
+            let (x, xoff) = (xcoord as f32, params.offset.0 as f32);
+            let px = x86_64::_mm256_setr_ps(
+                (x + xoff) / h * 2.0 - 1.8,
+                (x + 1.0 + xoff) / h * 2.0 - 1.8,
+                (x + 2.0 + xoff) / h * 2.0 - 1.8,
+                (x + 3.0 + xoff) / h * 2.0 - 1.8,
+                (x + 4.0 + xoff) / h * 2.0 - 1.8,
+                (x + 5.0 + xoff) / h * 2.0 - 1.8,
+                (x + 6.0 + xoff) / h * 2.0 - 1.8,
+                (x + 7.0 + xoff) / h * 2.0 - 1.8,
+            );
+            let pixel = Complex32x8::new(px, py);
+            vm.start(pixel, if J { seed } else { pixel });
+            let mut active = x86_64::_mm256_castsi256_ps(x86_64::_mm256_cmpgt_epi32(
+                x86_64::_mm256_set1_epi32(lanes as i32),
+                lane,
+            ));
+            let mut iterations = x86_64::_mm256_setzero_si256();
+
+            for _ in 0..max_iterations {
+                active = x86_64::_mm256_and_ps(active, vm.bailout());
+                if x86_64::_mm256_movemask_ps(active) == 0 {
+                    break;
+                }
+                iterations =
+                    x86_64::_mm256_sub_epi32(iterations, x86_64::_mm256_castps_si256(active));
+                vm.step();
+            }
+
+            x86_64::_mm256_storeu_si256(iter.as_mut_ptr() as *mut x86_64::__m256i, iterations);
+            block[ycoord - hstart][xcoord..xcoord + lanes].copy_from_slice(&iter[..lanes]);
+        }
+    }
+    (hstart, hend)
+}
@@ -0,0 +1,123 @@
+use crate::fractal::{Block, IterationType};
+
+use super::lanes::{self, Complex, Kernel, Lanes, Real};
+use super::{FSignature, InstructionSet, Precision, RenderContext};
+
+// The escape-time loop of Mandelbrot, Julia and Burning Ship in either
+// plane, written once over `Lanes`. A block of lanes takes the pixels of a
+// row in turn, a lane retires for good once its pixel escapes, and the
+// lanes past the width of the last block of a row never start. Every lane reaches its count through
+// the operations of the scalar kernel in the same order. Lanes with FMA
+// fuse the multiplies into the adds instead, and fold `+ c` into squaring
+// to shorten the chain every iteration waits on, so near the boundary their
+// counts can differ from the scalar kernel's. The one other exception is
+// Burning Ship at f32, whose scalar kernels compute in f64.
+
+pub const MANDELBROT: u8 = 0;
+pub const JULIA: u8 = 1;
+pub const BURNING_SHIP: u8 = 2;
+pub const BURNING_SHIP_JULIA: u8 = BURNING_SHIP | JULIA;
+
+/// The kernel of fractal `F` for `prec` on `ins`, `None` where it renders
+/// with its scalar kernel
+pub fn fn_<const F: u8>(prec: Precision, ins: InstructionSet) -> Option<FSignature> {
+    match prec {
+        Precision::Fixed => None,
+        _ => lanes::kernel::<Escape<F>>(prec, ins),
+    }
+}
+
+struct Escape<const F: u8>;
+
+impl<const F: u8> Kernel for Escape<F> {
+    #[inline(always)]
+    unsafe fn render<L: Lanes>(ctx: RenderContext) -> Block {
+        escape::<L, F>(ctx)
+    }
+}
+
+impl<L: Lanes> Complex<L> {
+    // self^power + c. Squaring with FMA rounds x^2 + (re c - y^2) and
+    // y * 2x + im c, two dependent multiply-adds instead of a multiply, a
+    // subtraction and the add of `c`.
+    #[inline(always)]
+    unsafe fn pow_add(self, power: u32, c: Self) -> Self {
+        match power {
+            2 if L::FMA => {
+                let (x, y) = (self.real, self.img);
+                Complex {
+                    real: x.mul_add(x, y.neg_mul_add(y, c.real)),
+                    img: y.mul_add(x.add(x), c.img),
+                }
+            }
+            _ => self.ipow(power).add(c),
+        }
+    }
+
+    // (-|re z|, |im z|), negating as the scalar Burning Ship does
+    #[inline(always)]
+    unsafe fn fold(self) -> Self {
+        let zero = L::splat(L::Real::ZERO);
+        Complex {
+            real: L::select(self.real.gt(zero), self.real.neg(), self.real),
+            img: L::select(self.img.gt(zero), self.img, self.img.neg()),
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn escape<L: Lanes, const F: u8>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        pow,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let burning_ship = F & BURNING_SHIP != 0;
+    let origin = match burning_ship {
+        true => (-2.0, -0.5),
+        false => (-1.8, -1.0),
+    };
+    let four = L::splat(L::Real::from_f64(4.0));
+    let start: IterationType = if burning_ship { 1 } else { 0 };
+    let seed = Complex::<L>::splat(
+        L::Real::from_f64(params.seed.real),
+        L::Real::from_f64(params.seed.img),
+    );
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        for xcoord in (0..width).step_by(L::LANES) {
+            let lanes = L::LANES.min(width - xcoord);
+            let pixel = Complex::<L>::pixels(xcoord, ycoord, height, params.offset, origin);
+            let c = if F & JULIA != 0 { seed } else { pixel };
+            let mut z = pixel;
+            let mut iterations = L::counter(start);
+            let mut active = L::first(lanes);
+            // Every lane still active has the same count, so the limit is
+            // checked once for all of them
+            for _ in start..max_iterations {
+                active = L::and(active, z.abs_sq().lt(four));
+                if !L::any(active) {
+                    break;
+                }
+                if burning_ship {
+                    z = z.fold();
+                }
+                z = z.pow_add(pow, c);
+                iterations = L::count(iterations, active);
+            }
+            L::store(
+                iterations,
+                &mut block[ycoord - hstart][xcoord..xcoord + lanes],
+            );
+        }
+    }
+    (hstart, hend)
+}
@@ -0,0 +1,142 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crate::utils::image::Color;
+
+use super::pool::Pool;
+use super::{
+    draw_tiled, Antialias, Block, ColorMode, FractalKernel, FrameBuffer, Listener, Params, Scene,
+};
+
+/// Everything a frame needs, owned so the job can outlive the fractal
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub max_iter: u16,
+    pub pow: u32,
+    pub pool: Arc<Pool>,
+    pub func: Arc<dyn FractalKernel>,
+    pub params: Params,
+    pub palette: Vec<Color>,
+    pub mode: ColorMode,
+    pub antialias: Antialias,
+}
+
+// Shared between the job handle and its threads
+struct State {
+    cancel: Arc<AtomicBool>,
+    rows: AtomicUsize,
+    total: usize,
+}
+
+/// A frame rendering in the background, see `Fractal::render_job`.
+/// Dropping the job cancels it.
+pub struct RenderJob {
+    state: Arc<State>,
+    handle: Option<JoinHandle<Option<Vec<Color>>>>,
+}
+
+impl RenderJob {
+    pub(super) fn spawn(
+        mut frame: Frame,
+        on_block: impl Fn(Block, &[Color]) + Send + Sync + 'static,
+    ) -> Self {
+        // Refinement goes over every row a second time
+        let passes = match frame.antialias {
+            Antialias::Off => 1,
+            _ => 2,
+        };
+        let state = Arc::new(State {
+            cancel: Arc::new(AtomicBool::new(false)),
+            rows: AtomicUsize::new(0),
+            total: frame.height * passes,
+        });
+        frame.params.cancel = Some(state.cancel.clone());
+        let shared = state.clone();
+        let handle = std::thread::spawn(move || render(frame, &shared, &on_block));
+        RenderJob {
+            state,
+            handle: Some(handle),
+        }
+    }
+
+    /// Share of the frame rendered so far, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.state.rows.load(Ordering::Relaxed) as f32 / self.state.total.max(1) as f32
+    }
+
+    /// Ask the job to stop, kernels finish the row they are on
+    pub fn cancel(&self) {
+        self.state.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Block until the job ends and return the frame, laid out like
+    /// `Image`, or `None` if the job was cancelled
+    pub fn wait(mut self) -> Option<Vec<Color>> {
+        match self.handle.take()?.join() {
+            Ok(pixels) => pixels,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl Drop for RenderJob {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+fn render(
+    frame: Frame,
+    state: &State,
+    on_block: &(dyn Fn(Block, &[Color]) + Sync),
+) -> Option<Vec<Color>> {
+    let Frame {
+        width,
+        height,
+        max_iter,
+        pow,
+        pool,
+        func,
+        params,
+        palette,
+        mode,
+        antialias,
+    } = frame;
+    let scene = Scene {
+        width,
+        height,
+        max_iter,
+        pow,
+        func: &*func,
+        params: &params,
+        palette: &palette,
+        mode,
+        antialias,
+    };
+    let mut blocks = FrameBuffer::new(width, height);
+    let mut values = FrameBuffer::new(width, height);
+    let mut image = vec![Color { r: 0, g: 0, b: 0 }; width * height];
+    // This thread works the pool's tiles along with its workers
+    let listener = Listener {
+        rows: &state.rows,
+        on_block,
+    };
+    draw_tiled(
+        &mut image,
+        &pool,
+        &scene,
+        &mut blocks,
+        &mut values,
+        Some(&listener),
+    );
+    if params.cancelled() {
+        return None;
+    }
+    Some(image)
+}
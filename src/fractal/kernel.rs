@@ -0,0 +1,48 @@
+use std::ops::Range;
+
+use super::{Block, FSignature, Params, Rows};
+
+/// One call of a kernel: rows `rows` of a `width` x `height` frame, written
+/// to `block` and `values`, which are indexed from the first of those rows
+pub struct RenderContext<'a> {
+    pub rows: Range<usize>,
+    pub width: usize,
+    pub height: usize,
+    pub max_iter: u32,
+    /// Integer exponent of the formulas that have one
+    pub pow: u32,
+    /// Iteration count of every pixel
+    pub block: Rows<'a, u32>,
+    /// Value the coloring reads for every pixel, if it reads one
+    pub values: Rows<'a, f32>,
+    pub params: &'a Params,
+}
+
+/// Renders rows of a fractal. The built-in kernels implement it, others are
+/// rendered through `FractalType::Registered`, see `Fractal::register`.
+pub trait FractalKernel: Send + Sync {
+    /// Render `ctx.rows` and return the rows rendered, which stop short of
+    /// the end once `ctx.params.cancelled()`. Counts past `ctx.max_iter` are
+    /// colored as `ctx.max_iter`.
+    fn render(&self, ctx: RenderContext) -> Block;
+}
+
+// A built-in kernel, only made for a CPU that runs the instruction set it
+// was picked for
+pub(super) struct Native(FSignature);
+
+impl Native {
+    /// # Safety
+    ///
+    /// The CPU must run the instruction set `kernel` was picked for.
+    pub(super) unsafe fn new(kernel: FSignature) -> Self {
+        Native(kernel)
+    }
+}
+
+impl FractalKernel for Native {
+    #[inline(always)]
+    fn render(&self, ctx: RenderContext) -> Block {
+        unsafe { (self.0)(ctx) }
+    }
+}
@@ -0,0 +1,1094 @@
+use std::f64::consts::{FRAC_2_PI, FRAC_PI_2, FRAC_PI_6, PI};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::utils::complex::ipow_with;
+
+use super::{Block, FSignature, InstructionSet, IterationType, Precision, RenderContext};
+
+// Vectors of floating-point lanes, the operations the vector kernels are
+// written against, and the functions that build a kernel written once over
+// them for every instruction set. Each vector also has a counter vector of
+// integers as wide as its lanes, so f64 lanes count in 64-bit integers and
+// f32 lanes in 32-bit ones. Only the multiply-adds round differently
+// between implementations, once where `FMA` is set and twice where it is
+// not. Unfused, every operation rounds like its scalar counterpart, except
+// for `atan2` and `sin_cos`, which approximate libm to a few ulp.
+
+/// The most lanes of any implementation, 16 f32 lanes in AVX-512
+pub const MAX_LANES: usize = 16;
+
+/// The floating-point types lanes hold
+pub trait Real:
+    Copy
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const MANTISSA_DIGITS: u32;
+
+    fn from_f64(value: f64) -> Self;
+    fn from_usize(value: usize) -> Self;
+    fn to_f64(self) -> f64;
+    // What `Array` needs beyond the operators, the x86 vectors have their
+    // own instructions
+    #[cfg(feature = "simd-portable")]
+    fn abs(self) -> Self;
+    #[cfg(feature = "simd-portable")]
+    fn sqrt(self) -> Self;
+    #[cfg(feature = "simd-portable")]
+    fn copysign(self, sign: Self) -> Self;
+}
+
+impl Real for f64 {
+    const ZERO: Self = 0.0;
+    const ONE: Self = 1.0;
+    const MANTISSA_DIGITS: u32 = f64::MANTISSA_DIGITS;
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    #[inline(always)]
+    fn from_usize(value: usize) -> Self {
+        value as f64
+    }
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    #[cfg(feature = "simd-portable")]
+    #[inline(always)]
+    fn abs(self) -> Self {
+        self.abs()
+    }
+
+    #[cfg(feature = "simd-portable")]
+    #[inline(always)]
+    fn sqrt(self) -> Self {
+        self.sqrt()
+    }
+
+    #[cfg(feature = "simd-portable")]
+    #[inline(always)]
+    fn copysign(self, sign: Self) -> Self {
+        self.copysign(sign)
+    }
+}
+
+impl Real for f32 {
+    const ZERO: Self = 0.0;
+    const ONE: Self = 1.0;
+    const MANTISSA_DIGITS: u32 = f32::MANTISSA_DIGITS;
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    #[inline(always)]
+    fn from_usize(value: usize) -> Self {
+        value as f32
+    }
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    #[cfg(feature = "simd-portable")]
+    #[inline(always)]
+    fn abs(self) -> Self {
+        self.abs()
+    }
+
+    #[cfg(feature = "simd-portable")]
+    #[inline(always)]
+    fn sqrt(self) -> Self {
+        self.sqrt()
+    }
+
+    #[cfg(feature = "simd-portable")]
+    #[inline(always)]
+    fn copysign(self, sign: Self) -> Self {
+        self.copysign(sign)
+    }
+}
+
+/// `LANES` floating-point numbers operated on together. The methods are
+/// unsafe because the x86 implementations need their instruction set, they
+/// are always inlined into a function that enables it.
+pub trait Lanes: Copy {
+    type Real: Real;
+    /// One flag per lane, what comparisons return
+    type Mask: Copy;
+    /// One iteration count per lane
+    type Counter: Copy;
+    const LANES: usize;
+    /// Whether the multiply-adds round once
+    const FMA: bool;
+
+    unsafe fn splat(value: Self::Real) -> Self;
+    /// `first`, `first + 1`, `first + 2`, ... across the lanes
+    unsafe fn ramp(first: Self::Real) -> Self;
+    unsafe fn add(self, b: Self) -> Self;
+    unsafe fn sub(self, b: Self) -> Self;
+    unsafe fn mul(self, b: Self) -> Self;
+    unsafe fn div(self, b: Self) -> Self;
+    unsafe fn neg(self) -> Self;
+    /// `self * b + c`, fused where the implementation has FMA
+    unsafe fn mul_add(self, b: Self, c: Self) -> Self;
+    /// `self * b - c`, fused where the implementation has FMA
+    unsafe fn mul_sub(self, b: Self, c: Self) -> Self;
+    /// `c - self * b`, fused where the implementation has FMA
+    unsafe fn neg_mul_add(self, b: Self, c: Self) -> Self;
+    unsafe fn abs(self) -> Self;
+    unsafe fn sqrt(self) -> Self;
+    /// The lesser of the two, `b` where they are equal or either is NaN
+    unsafe fn min(self, b: Self) -> Self;
+    /// The greater of the two, `b` where they are equal or either is NaN
+    unsafe fn max(self, b: Self) -> Self;
+    /// The magnitude of `self` with the sign bit of `sign`
+    unsafe fn copysign(self, sign: Self) -> Self;
+    unsafe fn lt(self, b: Self) -> Self::Mask;
+    unsafe fn gt(self, b: Self) -> Self::Mask;
+    /// `a` in the lanes `mask` sets, `b` in the others
+    unsafe fn select(mask: Self::Mask, a: Self, b: Self) -> Self;
+    unsafe fn and(a: Self::Mask, b: Self::Mask) -> Self::Mask;
+    unsafe fn any(mask: Self::Mask) -> bool;
+    /// Write the first `out.len()` lanes
+    unsafe fn write(self, out: &mut [Self::Real]);
+
+    /// The first `lanes` lanes set
+    #[inline(always)]
+    unsafe fn first(lanes: usize) -> Self::Mask {
+        Self::ramp(Self::Real::ZERO).lt(Self::splat(Self::Real::from_usize(lanes)))
+    }
+
+    unsafe fn counter(start: IterationType) -> Self::Counter;
+    /// Add one to the counts of the lanes `mask` sets
+    unsafe fn count(counter: Self::Counter, mask: Self::Mask) -> Self::Counter;
+    /// Write the counts of the first `out.len()` lanes
+    unsafe fn store(counter: Self::Counter, out: &mut [IterationType]);
+}
+
+/// A kernel written once over `Lanes`, see `kernel`
+pub trait Kernel {
+    /// Whether the AVX2 kernels fuse multiply-adds on CPUs with FMA. Orbits
+    /// that underflow take a microcode assist per subnormal, which fused
+    /// operations take more often on some CPUs.
+    #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+    const FMA: bool = true;
+
+    /// Render `ctx` with the lanes `L`, the CPU has to run their
+    /// instruction set
+    unsafe fn render<L: Lanes>(ctx: RenderContext) -> Block;
+}
+
+/// `K` built for `prec` on `ins`, `None` where it renders with its scalar
+/// kernel. There are no fixed-point lanes, fixed point renders at f64.
+pub fn kernel<K: Kernel>(prec: Precision, ins: InstructionSet) -> Option<FSignature> {
+    match (ins, prec) {
+        // As many lanes as one AVX-512 register holds, narrower targets run
+        // a block as several vectors
+        #[cfg(feature = "simd-portable")]
+        (InstructionSet::Portable, Precision::F64 | Precision::Fixed) => {
+            Some(portable::<K, Array<f64, 8>>)
+        }
+        #[cfg(feature = "simd-portable")]
+        (InstructionSet::Portable, Precision::F32) => Some(portable::<K, Array<f32, 16>>),
+        #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+        (InstructionSet::AVX512, Precision::F64 | Precision::Fixed) => Some(avx512::<K, F64x8>),
+        #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+        (InstructionSet::AVX512, Precision::F32) => Some(avx512::<K, F32x16>),
+        #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+        (InstructionSet::AVX, Precision::F64 | Precision::Fixed)
+            if K::FMA && is_x86_feature_detected!("fma") =>
+        {
+            Some(avx2_fma::<K, F64x4<true>>)
+        }
+        #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+        (InstructionSet::AVX, Precision::F32) if K::FMA && is_x86_feature_detected!("fma") => {
+            Some(avx2_fma::<K, F32x8<true>>)
+        }
+        #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+        (InstructionSet::AVX, Precision::F64 | Precision::Fixed) => Some(avx2::<K, F64x4<false>>),
+        #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+        (InstructionSet::AVX, Precision::F32) => Some(avx2::<K, F32x8<false>>),
+        #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+        (InstructionSet::SSE, Precision::F64 | Precision::Fixed) => Some(sse2::<K, F64x2<false>>),
+        #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+        (InstructionSet::SSE, Precision::F32) => Some(sse2::<K, F32x4<false>>),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "simd-portable")]
+fn portable<K: Kernel, L: Lanes>(ctx: RenderContext) -> Block {
+    unsafe { K::render::<L>(ctx) }
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+#[target_feature(enable = "sse2")]
+unsafe fn sse2<K: Kernel, L: Lanes>(ctx: RenderContext) -> Block {
+    K::render::<L>(ctx)
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+#[target_feature(enable = "avx2")]
+unsafe fn avx2<K: Kernel, L: Lanes>(ctx: RenderContext) -> Block {
+    K::render::<L>(ctx)
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn avx2_fma<K: Kernel, L: Lanes>(ctx: RenderContext) -> Block {
+    K::render::<L>(ctx)
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+#[target_feature(enable = "avx512f")]
+unsafe fn avx512<K: Kernel, L: Lanes>(ctx: RenderContext) -> Block {
+    K::render::<L>(ctx)
+}
+
+/// One complex number per lane
+#[derive(Clone, Copy)]
+pub struct Complex<L> {
+    pub real: L,
+    pub img: L,
+}
+
+impl<L: Lanes> Complex<L> {
+    #[inline(always)]
+    pub unsafe fn splat(real: L::Real, img: L::Real) -> Self {
+        Complex {
+            real: L::splat(real),
+            img: L::splat(img),
+        }
+    }
+
+    /// The pixels of row `ycoord` from column `xcoord` on, one per lane,
+    /// placed like the pixels of the scalar kernels at
+    /// (x + offset) / height * 2 + origin
+    #[inline(always)]
+    pub unsafe fn pixels(
+        xcoord: usize,
+        ycoord: usize,
+        height: usize,
+        offset: (f64, f64),
+        origin: (f64, f64),
+    ) -> Self {
+        let h = L::Real::from_usize(height);
+        let two = L::Real::from_f64(2.0);
+        let x = L::ramp(L::Real::from_usize(xcoord)).add(L::splat(L::Real::from_f64(offset.0)));
+        let y = L::Real::from_usize(ycoord) + L::Real::from_f64(offset.1);
+        Complex {
+            real: x
+                .div(L::splat(h))
+                .mul(L::splat(two))
+                .add(L::splat(L::Real::from_f64(origin.0))),
+            img: L::splat(y / h * two + L::Real::from_f64(origin.1)),
+        }
+    }
+
+    /// `a` in the lanes `mask` sets, `b` in the others
+    #[inline(always)]
+    pub unsafe fn select(mask: L::Mask, a: Self, b: Self) -> Self {
+        Complex {
+            real: L::select(mask, a.real, b.real),
+            img: L::select(mask, a.img, b.img),
+        }
+    }
+
+    #[inline(always)]
+    pub unsafe fn add(self, b: Self) -> Self {
+        Complex {
+            real: self.real.add(b.real),
+            img: self.img.add(b.img),
+        }
+    }
+
+    #[inline(always)]
+    pub unsafe fn sub(self, b: Self) -> Self {
+        Complex {
+            real: self.real.sub(b.real),
+            img: self.img.sub(b.img),
+        }
+    }
+
+    #[inline(always)]
+    pub unsafe fn conjugate(self) -> Self {
+        Complex {
+            real: self.real,
+            img: self.img.neg(),
+        }
+    }
+
+    /// In the order of `Mul` for `Complex64`
+    #[inline(always)]
+    pub unsafe fn cmul(self, b: Self) -> Self {
+        Complex {
+            real: self.real.mul_sub(b.real, self.img.mul(b.img)),
+            img: self.img.mul_add(b.real, self.real.mul(b.img)),
+        }
+    }
+
+    #[inline(always)]
+    pub unsafe fn abs_sq(self) -> L {
+        self.real.mul_add(self.real, self.img.mul(self.img))
+    }
+
+    #[inline(always)]
+    pub unsafe fn ipow(self, power: u32) -> Self {
+        let one = (L::splat(L::Real::ONE), L::splat(L::Real::ZERO));
+        let (real, img) = ipow_with((self.real, self.img), power, one, |(ar, ai), (br, bi)| {
+            let (a, b) = (Complex { real: ar, img: ai }, Complex { real: br, img: bi });
+            let product = a.cmul(b);
+            (product.real, product.img)
+        });
+        Complex { real, img }
+    }
+
+    /// Four-quadrant argument, see `atan2`
+    #[inline(always)]
+    pub unsafe fn arg(self) -> L {
+        atan2(self.img, self.real)
+    }
+}
+
+#[inline(always)]
+unsafe fn constant<L: Lanes>(value: f64) -> L {
+    L::splat(L::Real::from_f64(value))
+}
+
+// The f64 or the f32 coefficients, after the lanes
+#[inline(always)]
+fn series<L: Lanes>(f64: &'static [f64], f32: &'static [f64]) -> &'static [f64] {
+    match L::Real::MANTISSA_DIGITS > f32::MANTISSA_DIGITS {
+        true => f64,
+        false => f32,
+    }
+}
+
+#[inline(always)]
+unsafe fn horner<L: Lanes>(x: L, coeff: &[f64]) -> L {
+    (coeff[1..].iter()).fold(constant(coeff[0]), |acc, &c| acc.mul_add(x, constant(c)))
+}
+
+// Integer-valued lanes nearest `x`, ties to even, for |x| well under
+// 2^(MANTISSA_DIGITS - 2): adding 1.5 * 2^(MANTISSA_DIGITS - 1) leaves no
+// bits below the point
+#[inline(always)]
+unsafe fn round<L: Lanes>(x: L) -> L {
+    let magic = constant::<L>(1.5 * (1u64 << (L::Real::MANTISSA_DIGITS - 1)) as f64);
+    x.add(magic).sub(magic)
+}
+
+// Lanes holding odd integers
+#[inline(always)]
+unsafe fn odd<L: Lanes>(k: L) -> L::Mask {
+    let half = k.mul(constant(0.5));
+    half.sub(round(half)).abs().gt(constant(0.25))
+}
+
+/// (sin x, cos x) in every lane, for |x| below ~1e5 at f64 and ~1e3 at f32.
+/// x is reduced by multiples of pi/2 to |r| <= pi/4, where truncated Taylor
+/// series give both.
+#[inline(always)]
+pub unsafe fn sin_cos<L: Lanes>(x: L) -> (L, L) {
+    let k = round(x.mul(constant(FRAC_2_PI)));
+    // pi/2 in parts whose products with k are exact, 1.5707963267341256 +
+    // 6.077100506506192e-11 for f64 and three for f32
+    let parts = series::<L>(
+        &[1.5707963267341256, 6.077100506506192e-11],
+        &[1.5703125, 4.837513e-4, 7.54979e-8],
+    );
+    let r = (parts.iter()).fold(x, |r, &part| k.neg_mul_add(constant(part), r));
+    let r2 = r.mul(r);
+    let s = r.mul(horner(
+        r2,
+        series::<L>(
+            &[
+                -1.0 / 1307674368000.0,
+                1.0 / 6227020800.0,
+                -1.0 / 39916800.0,
+                1.0 / 362880.0,
+                -1.0 / 5040.0,
+                1.0 / 120.0,
+                -1.0 / 6.0,
+                1.0,
+            ],
+            &[1.0 / 362880.0, -1.0 / 5040.0, 1.0 / 120.0, -1.0 / 6.0, 1.0],
+        ),
+    ));
+    let c = horner(
+        r2,
+        series::<L>(
+            &[
+                1.0 / 20922789888000.0,
+                -1.0 / 87178291200.0,
+                1.0 / 479001600.0,
+                -1.0 / 3628800.0,
+                1.0 / 40320.0,
+                -1.0 / 720.0,
+                1.0 / 24.0,
+                -0.5,
+                1.0,
+            ],
+            &[
+                -1.0 / 3628800.0,
+                1.0 / 40320.0,
+                -1.0 / 720.0,
+                1.0 / 24.0,
+                -0.5,
+                1.0,
+            ],
+        ),
+    );
+
+    // An odd k turns (sin, cos) a quarter, an odd k / 2 rounded down a half
+    let quarter = odd(k);
+    let (sin, cos) = (L::select(quarter, c, s), L::select(quarter, s.neg(), c));
+    let half = odd(k
+        .sub(L::select(quarter, constant(1.0), constant(0.0)))
+        .mul(constant(0.5)));
+    (
+        L::select(half, sin.neg(), sin),
+        L::select(half, cos.neg(), cos),
+    )
+}
+
+/// Four-quadrant arctangent of y / x in every lane, in [-pi, pi]. The ratio
+/// of the lesser to the greater magnitude is brought under tan(pi/12),
+/// where a truncated series gives its arctangent, and the octant is undone.
+#[inline(always)]
+pub unsafe fn atan2<L: Lanes>(y: L, x: L) -> L {
+    let (ay, ax) = (y.abs(), x.abs());
+    let (hi, lo) = (ay.max(ax), ay.min(ax));
+    let zero = constant::<L>(0.0);
+    // t in [0, 1], left at 0 for atan2(0, 0)
+    let t = L::select(hi.gt(zero), lo.div(hi), zero);
+    // atan(t) = pi/6 + atan((t sqrt3 - 1) / (t + sqrt3))
+    let sqrt3 = constant(1.7320508075688772);
+    let reduce = t.gt(constant(0.2679491924311227));
+    let t = L::select(reduce, t.mul_sub(sqrt3, constant(1.0)).div(t.add(sqrt3)), t);
+    let r = t.mul(horner(
+        t.mul(t),
+        series::<L>(
+            &[
+                1.0 / 29.0,
+                -1.0 / 27.0,
+                1.0 / 25.0,
+                -1.0 / 23.0,
+                1.0 / 21.0,
+                -1.0 / 19.0,
+                1.0 / 17.0,
+                -1.0 / 15.0,
+                1.0 / 13.0,
+                -1.0 / 11.0,
+                1.0 / 9.0,
+                -1.0 / 7.0,
+                1.0 / 5.0,
+                -1.0 / 3.0,
+                1.0,
+            ],
+            &[
+                -1.0 / 15.0,
+                1.0 / 13.0,
+                -1.0 / 11.0,
+                1.0 / 9.0,
+                -1.0 / 7.0,
+                1.0 / 5.0,
+                -1.0 / 3.0,
+                1.0,
+            ],
+        ),
+    ));
+    let r = L::select(reduce, r.add(constant(FRAC_PI_6)), r);
+    let r = L::select(ay.gt(ax), constant::<L>(FRAC_PI_2).sub(r), r);
+    // The sign bit of x, -0 included, picks the left half-plane
+    let left = constant::<L>(1.0).copysign(x).lt(zero);
+    L::select(left, constant::<L>(PI).sub(r), r).copysign(y)
+}
+
+/// Lanes in a plain array, for LLVM to vectorize with whatever the target
+/// has, SSE2 on x86_64 or NEON on aarch64. Rust never contracts operations
+/// into fused multiply-adds on its own, so these round like the scalar
+/// kernels. Masks hold 0 or 1 per lane as integers, LLVM keeps those in
+/// vector registers where it takes arrays of `bool` apart lane by lane.
+#[cfg(feature = "simd-portable")]
+#[derive(Clone, Copy)]
+pub struct Array<T, const N: usize>([T; N]);
+
+#[cfg(feature = "simd-portable")]
+impl<T: Real, const N: usize> Lanes for Array<T, N> {
+    type Real = T;
+    type Mask = [IterationType; N];
+    type Counter = [IterationType; N];
+    const LANES: usize = N;
+    const FMA: bool = false;
+
+    #[inline(always)]
+    unsafe fn splat(value: T) -> Self {
+        Array([value; N])
+    }
+
+    #[inline(always)]
+    unsafe fn ramp(first: T) -> Self {
+        Array(std::array::from_fn(|k| first + T::from_usize(k)))
+    }
+
+    #[inline(always)]
+    unsafe fn add(self, b: Self) -> Self {
+        Array(std::array::from_fn(|k| self.0[k] + b.0[k]))
+    }
+
+    #[inline(always)]
+    unsafe fn sub(self, b: Self) -> Self {
+        Array(std::array::from_fn(|k| self.0[k] - b.0[k]))
+    }
+
+    #[inline(always)]
+    unsafe fn mul(self, b: Self) -> Self {
+        Array(std::array::from_fn(|k| self.0[k] * b.0[k]))
+    }
+
+    #[inline(always)]
+    unsafe fn div(self, b: Self) -> Self {
+        Array(std::array::from_fn(|k| self.0[k] / b.0[k]))
+    }
+
+    #[inline(always)]
+    unsafe fn neg(self) -> Self {
+        Array(self.0.map(|x| -x))
+    }
+
+    #[inline(always)]
+    unsafe fn mul_add(self, b: Self, c: Self) -> Self {
+        self.mul(b).add(c)
+    }
+
+    #[inline(always)]
+    unsafe fn mul_sub(self, b: Self, c: Self) -> Self {
+        self.mul(b).sub(c)
+    }
+
+    #[inline(always)]
+    unsafe fn neg_mul_add(self, b: Self, c: Self) -> Self {
+        c.sub(self.mul(b))
+    }
+
+    #[inline(always)]
+    unsafe fn abs(self) -> Self {
+        Array(self.0.map(T::abs))
+    }
+
+    #[inline(always)]
+    unsafe fn sqrt(self) -> Self {
+        Array(self.0.map(T::sqrt))
+    }
+
+    #[inline(always)]
+    unsafe fn min(self, b: Self) -> Self {
+        Array(std::array::from_fn(|k| {
+            if self.0[k] < b.0[k] {
+                self.0[k]
+            } else {
+                b.0[k]
+            }
+        }))
+    }
+
+    #[inline(always)]
+    unsafe fn max(self, b: Self) -> Self {
+        Array(std::array::from_fn(|k| {
+            if self.0[k] > b.0[k] {
+                self.0[k]
+            } else {
+                b.0[k]
+            }
+        }))
+    }
+
+    #[inline(always)]
+    unsafe fn copysign(self, sign: Self) -> Self {
+        Array(std::array::from_fn(|k| self.0[k].copysign(sign.0[k])))
+    }
+
+    #[inline(always)]
+    unsafe fn lt(self, b: Self) -> Self::Mask {
+        std::array::from_fn(|k| (self.0[k] < b.0[k]) as IterationType)
+    }
+
+    #[inline(always)]
+    unsafe fn gt(self, b: Self) -> Self::Mask {
+        std::array::from_fn(|k| (self.0[k] > b.0[k]) as IterationType)
+    }
+
+    #[inline(always)]
+    unsafe fn select(mask: Self::Mask, a: Self, b: Self) -> Self {
+        Array(std::array::from_fn(|k| {
+            if mask[k] != 0 {
+                a.0[k]
+            } else {
+                b.0[k]
+            }
+        }))
+    }
+
+    #[inline(always)]
+    unsafe fn and(a: Self::Mask, b: Self::Mask) -> Self::Mask {
+        std::array::from_fn(|k| a[k] & b[k])
+    }
+
+    #[inline(always)]
+    unsafe fn any(mask: Self::Mask) -> bool {
+        mask.iter().fold(0, |a, m| a | m) != 0
+    }
+
+    #[inline(always)]
+    unsafe fn write(self, out: &mut [T]) {
+        let lanes = out.len();
+        out.copy_from_slice(&self.0[..lanes]);
+    }
+
+    #[inline(always)]
+    unsafe fn counter(start: IterationType) -> Self::Counter {
+        [start; N]
+    }
+
+    #[inline(always)]
+    unsafe fn count(counter: Self::Counter, mask: Self::Mask) -> Self::Counter {
+        std::array::from_fn(|k| counter[k] + mask[k])
+    }
+
+    #[inline(always)]
+    unsafe fn store(counter: Self::Counter, out: &mut [IterationType]) {
+        let lanes = out.len();
+        out.copy_from_slice(&counter[..lanes]);
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+pub use x86::*;
+
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+mod x86 {
+    use super::{IterationType, Lanes};
+    use core::arch::x86_64::*;
+
+    /// 2 f64 lanes in an SSE2 register
+    #[derive(Clone, Copy)]
+    pub struct F64x2<const FMA: bool>(__m128d);
+    /// 4 f32 lanes in an SSE2 register
+    #[derive(Clone, Copy)]
+    pub struct F32x4<const FMA: bool>(__m128);
+    /// 4 f64 lanes in an AVX2 register
+    #[derive(Clone, Copy)]
+    pub struct F64x4<const FMA: bool>(__m256d);
+    /// 8 f32 lanes in an AVX2 register
+    #[derive(Clone, Copy)]
+    pub struct F32x8<const FMA: bool>(__m256);
+    /// 8 f64 lanes in an AVX-512 register, always fused
+    #[derive(Clone, Copy)]
+    pub struct F64x8(__m512d);
+    /// 16 f32 lanes in an AVX-512 register, always fused
+    #[derive(Clone, Copy)]
+    pub struct F32x16(__m512);
+
+    // SSE2 and AVX2 compare into a vector of all-ones or all-zeros lanes,
+    // which as integers is -1 or 0, so counting subtracts the mask
+    macro_rules! impl_lanes_for {
+        (
+            $name:ident, $vec:ty, $ivec:ty, $real:ty, $int:ty, $lanes:literal,
+            set1: $set1:ident, loadu: $loadu:ident, storeu: $storeu:ident,
+            add: $add:ident, sub: $sub:ident, mul: $mul:ident, div: $div:ident,
+            sqrt: $sqrt:ident, min: $min:ident, max: $max:ident,
+            xor: $xor:ident, and: $and:ident, andnot: $andnot:ident, or: $or:ident,
+            fmadd: $fmadd:ident, fmsub: $fmsub:ident, fnmadd: $fnmadd:ident,
+            lt: $lt:path, gt: $gt:path, movemask: $movemask:ident,
+            cast: $cast:ident, iset1: $iset1:ident, isub: $isub:ident,
+            istoreu: $istoreu:ident
+        ) => {
+            impl<const FMA: bool> Lanes for $name<FMA> {
+                type Real = $real;
+                type Mask = $vec;
+                type Counter = $ivec;
+                const LANES: usize = $lanes;
+                const FMA: bool = FMA;
+
+                #[inline(always)]
+                unsafe fn splat(value: $real) -> Self {
+                    $name($set1(value))
+                }
+
+                #[inline(always)]
+                unsafe fn ramp(first: $real) -> Self {
+                    let steps: [$real; $lanes] = std::array::from_fn(|k| k as $real);
+                    $name($add($set1(first), $loadu(steps.as_ptr())))
+                }
+
+                #[inline(always)]
+                unsafe fn add(self, b: Self) -> Self {
+                    $name($add(self.0, b.0))
+                }
+
+                #[inline(always)]
+                unsafe fn sub(self, b: Self) -> Self {
+                    $name($sub(self.0, b.0))
+                }
+
+                #[inline(always)]
+                unsafe fn mul(self, b: Self) -> Self {
+                    $name($mul(self.0, b.0))
+                }
+
+                #[inline(always)]
+                unsafe fn div(self, b: Self) -> Self {
+                    $name($div(self.0, b.0))
+                }
+
+                #[inline(always)]
+                unsafe fn neg(self) -> Self {
+                    $name($xor(self.0, $set1(-0.0)))
+                }
+
+                #[inline(always)]
+                unsafe fn mul_add(self, b: Self, c: Self) -> Self {
+                    match FMA {
+                        true => $name($fmadd(self.0, b.0, c.0)),
+                        false => self.mul(b).add(c),
+                    }
+                }
+
+                #[inline(always)]
+                unsafe fn mul_sub(self, b: Self, c: Self) -> Self {
+                    match FMA {
+                        true => $name($fmsub(self.0, b.0, c.0)),
+                        false => self.mul(b).sub(c),
+                    }
+                }
+
+                #[inline(always)]
+                unsafe fn neg_mul_add(self, b: Self, c: Self) -> Self {
+                    match FMA {
+                        true => $name($fnmadd(self.0, b.0, c.0)),
+                        false => c.sub(self.mul(b)),
+                    }
+                }
+
+                #[inline(always)]
+                unsafe fn abs(self) -> Self {
+                    $name($andnot($set1(-0.0), self.0))
+                }
+
+                #[inline(always)]
+                unsafe fn sqrt(self) -> Self {
+                    $name($sqrt(self.0))
+                }
+
+                #[inline(always)]
+                unsafe fn min(self, b: Self) -> Self {
+                    $name($min(self.0, b.0))
+                }
+
+                #[inline(always)]
+                unsafe fn max(self, b: Self) -> Self {
+                    $name($max(self.0, b.0))
+                }
+
+                #[inline(always)]
+                unsafe fn copysign(self, sign: Self) -> Self {
+                    let bit = $set1(-0.0);
+                    $name($or($andnot(bit, self.0), $and(bit, sign.0)))
+                }
+
+                #[inline(always)]
+                unsafe fn lt(self, b: Self) -> $vec {
+                    $lt(self.0, b.0)
+                }
+
+                #[inline(always)]
+                unsafe fn gt(self, b: Self) -> $vec {
+                    $gt(self.0, b.0)
+                }
+
+                #[inline(always)]
+                unsafe fn select(mask: $vec, a: Self, b: Self) -> Self {
+                    $name($or($and(mask, a.0), $andnot(mask, b.0)))
+                }
+
+                #[inline(always)]
+                unsafe fn and(a: $vec, b: $vec) -> $vec {
+                    $and(a, b)
+                }
+
+                #[inline(always)]
+                unsafe fn any(mask: $vec) -> bool {
+                    $movemask(mask) != 0
+                }
+
+                #[inline(always)]
+                unsafe fn write(self, out: &mut [$real]) {
+                    let mut lanes = [0.0; $lanes];
+                    $storeu(lanes.as_mut_ptr(), self.0);
+                    let len = out.len();
+                    out.copy_from_slice(&lanes[..len]);
+                }
+
+                #[inline(always)]
+                unsafe fn counter(start: IterationType) -> $ivec {
+                    $iset1(start as _)
+                }
+
+                #[inline(always)]
+                unsafe fn count(counter: $ivec, mask: $vec) -> $ivec {
+                    $isub(counter, $cast(mask))
+                }
+
+                #[inline(always)]
+                unsafe fn store(counter: $ivec, out: &mut [IterationType]) {
+                    let mut counts = [0 as $int; $lanes];
+                    $istoreu(counts.as_mut_ptr() as *mut $ivec, counter);
+                    for (out, count) in out.iter_mut().zip(counts) {
+                        *out = count as IterationType;
+                    }
+                }
+            }
+        };
+    }
+
+    impl_lanes_for!(
+        F64x2, __m128d, __m128i, f64, i64, 2,
+        set1: _mm_set1_pd, loadu: _mm_loadu_pd, storeu: _mm_storeu_pd,
+        add: _mm_add_pd, sub: _mm_sub_pd, mul: _mm_mul_pd, div: _mm_div_pd,
+        sqrt: _mm_sqrt_pd, min: _mm_min_pd, max: _mm_max_pd,
+        xor: _mm_xor_pd, and: _mm_and_pd, andnot: _mm_andnot_pd, or: _mm_or_pd,
+        fmadd: _mm_fmadd_pd, fmsub: _mm_fmsub_pd, fnmadd: _mm_fnmadd_pd,
+        lt: _mm_cmplt_pd, gt: _mm_cmpgt_pd, movemask: _mm_movemask_pd,
+        cast: _mm_castpd_si128, iset1: _mm_set1_epi64x, isub: _mm_sub_epi64,
+        istoreu: _mm_storeu_si128
+    );
+    impl_lanes_for!(
+        F32x4, __m128, __m128i, f32, i32, 4,
+        set1: _mm_set1_ps, loadu: _mm_loadu_ps, storeu: _mm_storeu_ps,
+        add: _mm_add_ps, sub: _mm_sub_ps, mul: _mm_mul_ps, div: _mm_div_ps,
+        sqrt: _mm_sqrt_ps, min: _mm_min_ps, max: _mm_max_ps,
+        xor: _mm_xor_ps, and: _mm_and_ps, andnot: _mm_andnot_ps, or: _mm_or_ps,
+        fmadd: _mm_fmadd_ps, fmsub: _mm_fmsub_ps, fnmadd: _mm_fnmadd_ps,
+        lt: _mm_cmplt_ps, gt: _mm_cmpgt_ps, movemask: _mm_movemask_ps,
+        cast: _mm_castps_si128, iset1: _mm_set1_epi32, isub: _mm_sub_epi32,
+        istoreu: _mm_storeu_si128
+    );
+    impl_lanes_for!(
+        F64x4, __m256d, __m256i, f64, i64, 4,
+        set1: _mm256_set1_pd, loadu: _mm256_loadu_pd, storeu: _mm256_storeu_pd,
+        add: _mm256_add_pd, sub: _mm256_sub_pd, mul: _mm256_mul_pd, div: _mm256_div_pd,
+        sqrt: _mm256_sqrt_pd, min: _mm256_min_pd, max: _mm256_max_pd,
+        xor: _mm256_xor_pd, and: _mm256_and_pd, andnot: _mm256_andnot_pd, or: _mm256_or_pd,
+        fmadd: _mm256_fmadd_pd, fmsub: _mm256_fmsub_pd, fnmadd: _mm256_fnmadd_pd,
+        lt: _mm256_cmp_pd::<_CMP_LT_OQ>, gt: _mm256_cmp_pd::<_CMP_GT_OQ>,
+        movemask: _mm256_movemask_pd,
+        cast: _mm256_castpd_si256, iset1: _mm256_set1_epi64x, isub: _mm256_sub_epi64,
+        istoreu: _mm256_storeu_si256
+    );
+    impl_lanes_for!(
+        F32x8, __m256, __m256i, f32, i32, 8,
+        set1: _mm256_set1_ps, loadu: _mm256_loadu_ps, storeu: _mm256_storeu_ps,
+        add: _mm256_add_ps, sub: _mm256_sub_ps, mul: _mm256_mul_ps, div: _mm256_div_ps,
+        sqrt: _mm256_sqrt_ps, min: _mm256_min_ps, max: _mm256_max_ps,
+        xor: _mm256_xor_ps, and: _mm256_and_ps, andnot: _mm256_andnot_ps, or: _mm256_or_ps,
+        fmadd: _mm256_fmadd_ps, fmsub: _mm256_fmsub_ps, fnmadd: _mm256_fnmadd_ps,
+        lt: _mm256_cmp_ps::<_CMP_LT_OQ>, gt: _mm256_cmp_ps::<_CMP_GT_OQ>,
+        movemask: _mm256_movemask_ps,
+        cast: _mm256_castps_si256, iset1: _mm256_set1_epi32, isub: _mm256_sub_epi32,
+        istoreu: _mm256_storeu_si256
+    );
+
+    // AVX-512 compares into mask registers and has masked adds, and AVX512F
+    // has FMA but no floating-point xor, so negating flips the sign bits as
+    // integers
+    macro_rules! impl_lanes_avx512_for {
+        (
+            $name:ident, $vec:ty, $mask:ty, $real:ty, $int:ty, $lanes:literal,
+            set1: $set1:ident, loadu: $loadu:ident, storeu: $storeu:ident,
+            add: $add:ident, sub: $sub:ident, mul: $mul:ident, div: $div:ident,
+            abs: $abs:ident, sqrt: $sqrt:ident, min: $min:ident, max: $max:ident,
+            fmadd: $fmadd:ident, fmsub: $fmsub:ident, fnmadd: $fnmadd:ident,
+            cmp: $cmp:ident, blend: $blend:ident,
+            to_int: $to_int:ident, from_int: $from_int:ident, iset1: $iset1:ident,
+            iadd: $iadd:ident, sign: $sign:expr
+        ) => {
+            impl Lanes for $name {
+                type Real = $real;
+                type Mask = $mask;
+                type Counter = __m512i;
+                const LANES: usize = $lanes;
+                const FMA: bool = true;
+
+                #[inline(always)]
+                unsafe fn splat(value: $real) -> Self {
+                    $name($set1(value))
+                }
+
+                #[inline(always)]
+                unsafe fn ramp(first: $real) -> Self {
+                    let steps: [$real; $lanes] = std::array::from_fn(|k| k as $real);
+                    $name($add($set1(first), $loadu(steps.as_ptr())))
+                }
+
+                #[inline(always)]
+                unsafe fn add(self, b: Self) -> Self {
+                    $name($add(self.0, b.0))
+                }
+
+                #[inline(always)]
+                unsafe fn sub(self, b: Self) -> Self {
+                    $name($sub(self.0, b.0))
+                }
+
+                #[inline(always)]
+                unsafe fn mul(self, b: Self) -> Self {
+                    $name($mul(self.0, b.0))
+                }
+
+                #[inline(always)]
+                unsafe fn div(self, b: Self) -> Self {
+                    $name($div(self.0, b.0))
+                }
+
+                #[inline(always)]
+                unsafe fn neg(self) -> Self {
+                    $name($from_int(_mm512_xor_si512($to_int(self.0), $iset1($sign))))
+                }
+
+                #[inline(always)]
+                unsafe fn mul_add(self, b: Self, c: Self) -> Self {
+                    $name($fmadd(self.0, b.0, c.0))
+                }
+
+                #[inline(always)]
+                unsafe fn mul_sub(self, b: Self, c: Self) -> Self {
+                    $name($fmsub(self.0, b.0, c.0))
+                }
+
+                #[inline(always)]
+                unsafe fn neg_mul_add(self, b: Self, c: Self) -> Self {
+                    $name($fnmadd(self.0, b.0, c.0))
+                }
+
+                #[inline(always)]
+                unsafe fn abs(self) -> Self {
+                    $name($abs(self.0))
+                }
+
+                #[inline(always)]
+                unsafe fn sqrt(self) -> Self {
+                    $name($sqrt(self.0))
+                }
+
+                #[inline(always)]
+                unsafe fn min(self, b: Self) -> Self {
+                    $name($min(self.0, b.0))
+                }
+
+                #[inline(always)]
+                unsafe fn max(self, b: Self) -> Self {
+                    $name($max(self.0, b.0))
+                }
+
+                #[inline(always)]
+                unsafe fn copysign(self, sign: Self) -> Self {
+                    let bit = $iset1($sign);
+                    $name($from_int(_mm512_or_si512(
+                        _mm512_andnot_si512(bit, $to_int(self.0)),
+                        _mm512_and_si512(bit, $to_int(sign.0)),
+                    )))
+                }
+
+                #[inline(always)]
+                unsafe fn lt(self, b: Self) -> $mask {
+                    $cmp::<_CMP_LT_OQ>(self.0, b.0)
+                }
+
+                #[inline(always)]
+                unsafe fn gt(self, b: Self) -> $mask {
+                    $cmp::<_CMP_GT_OQ>(self.0, b.0)
+                }
+
+                #[inline(always)]
+                unsafe fn select(mask: $mask, a: Self, b: Self) -> Self {
+                    $name($blend(mask, b.0, a.0))
+                }
+
+                #[inline(always)]
+                unsafe fn and(a: $mask, b: $mask) -> $mask {
+                    a & b
+                }
+
+                #[inline(always)]
+                unsafe fn any(mask: $mask) -> bool {
+                    mask != 0
+                }
+
+                #[inline(always)]
+                unsafe fn write(self, out: &mut [$real]) {
+                    let mut lanes = [0.0; $lanes];
+                    $storeu(lanes.as_mut_ptr(), self.0);
+                    let len = out.len();
+                    out.copy_from_slice(&lanes[..len]);
+                }
+
+                #[inline(always)]
+                unsafe fn counter(start: IterationType) -> __m512i {
+                    $iset1(start as _)
+                }
+
+                #[inline(always)]
+                unsafe fn count(counter: __m512i, mask: $mask) -> __m512i {
+                    $iadd(counter, mask, counter, $iset1(1))
+                }
+
+                #[inline(always)]
+                unsafe fn store(counter: __m512i, out: &mut [IterationType]) {
+                    let mut counts = [0 as $int; $lanes];
+                    _mm512_storeu_si512(counts.as_mut_ptr() as *mut __m512i, counter);
+                    for (out, count) in out.iter_mut().zip(counts) {
+                        *out = count as IterationType;
+                    }
+                }
+            }
+        };
+    }
+
+    impl_lanes_avx512_for!(
+        F64x8, __m512d, __mmask8, f64, i64, 8,
+        set1: _mm512_set1_pd, loadu: _mm512_loadu_pd, storeu: _mm512_storeu_pd,
+        add: _mm512_add_pd, sub: _mm512_sub_pd, mul: _mm512_mul_pd, div: _mm512_div_pd,
+        abs: _mm512_abs_pd, sqrt: _mm512_sqrt_pd, min: _mm512_min_pd, max: _mm512_max_pd,
+        fmadd: _mm512_fmadd_pd, fmsub: _mm512_fmsub_pd, fnmadd: _mm512_fnmadd_pd,
+        cmp: _mm512_cmp_pd_mask,
+        blend: _mm512_mask_blend_pd,
+        to_int: _mm512_castpd_si512, from_int: _mm512_castsi512_pd, iset1: _mm512_set1_epi64,
+        iadd: _mm512_mask_add_epi64, sign: i64::MIN
+    );
+    impl_lanes_avx512_for!(
+        F32x16, __m512, __mmask16, f32, i32, 16,
+        set1: _mm512_set1_ps, loadu: _mm512_loadu_ps, storeu: _mm512_storeu_ps,
+        add: _mm512_add_ps, sub: _mm512_sub_ps, mul: _mm512_mul_ps, div: _mm512_div_ps,
+        abs: _mm512_abs_ps, sqrt: _mm512_sqrt_ps, min: _mm512_min_ps, max: _mm512_max_ps,
+        fmadd: _mm512_fmadd_ps, fmsub: _mm512_fmsub_ps, fnmadd: _mm512_fnmadd_ps,
+        cmp: _mm512_cmp_ps_mask,
+        blend: _mm512_mask_blend_ps,
+        to_int: _mm512_castps_si512, from_int: _mm512_castsi512_ps, iset1: _mm512_set1_epi32,
+        iadd: _mm512_mask_add_epi32, sign: i32::MIN
+    );
+}
@@ -0,0 +1,244 @@
+use crate::utils::complex::{Complex32, Complex64};
+
+use crate::fractal::{Block, IterationType};
+
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+use super::lanes::{self, Complex, Kernel, Lanes, Real, MAX_LANES};
+use super::{FSignature, InstructionSet, Precision, RenderContext};
+
+// z^n + c carried with its derivative, dz/dc in the parameter plane and
+// dz/dz_0 in Julia mode. Close to the set, z/dz points down the slope of
+// the distance estimate, so arg(z/dz) is the direction the surface faces.
+// That angle goes to the value buffer for the color stage to light, the
+// escape iteration to the iteration buffer.
+
+// A large bailout lets z/dz settle on its limit direction
+const BAILOUT: f64 = 100.0;
+
+pub fn fn_(julia: bool, prec: Precision, ins: InstructionSet) -> FSignature {
+    if julia {
+        kernel::<true>(prec, ins)
+    } else {
+        kernel::<false>(prec, ins)
+    }
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "simd-portable",
+        all(target_arch = "x86_64", feature = "simd-x86")
+    )),
+    allow(unused_variables)
+)]
+fn kernel<const J: bool>(prec: Precision, ins: InstructionSet) -> FSignature {
+    #[cfg(any(
+        feature = "simd-portable",
+        all(target_arch = "x86_64", feature = "simd-x86")
+    ))]
+    if let Some(kernel) = lanes::kernel::<Lighting<J>>(prec, ins) {
+        return kernel;
+    }
+    match prec {
+        Precision::F64 | Precision::Fixed => lighting::<J>,
+        Precision::F32 => lightingf32::<J>,
+    }
+}
+
+#[inline(always)]
+fn pixel<const J: bool>(
+    mut z: Complex64,
+    c: Complex64,
+    pow: u32,
+    max_iterations: u32,
+) -> (IterationType, f32) {
+    let n = Complex64::new(pow as f64, 0.0);
+    let one = Complex64::new(1.0, 0.0);
+    let mut dz = one;
+    let mut iterations = 0;
+    while iterations < max_iterations && z.abs_sq() < BAILOUT * BAILOUT {
+        let zn1 = z.ipow(pow.max(1) - 1);
+        dz = n * zn1 * dz;
+        if !J {
+            dz += one;
+        }
+        z = zn1 * z + &c;
+        iterations += 1;
+    }
+    (
+        iterations as IterationType,
+        (z * dz.conjugate()).arg() as f32,
+    )
+}
+
+#[inline(always)]
+fn pixelf32<const J: bool>(
+    mut z: Complex32,
+    c: Complex32,
+    pow: u32,
+    max_iterations: u32,
+) -> (IterationType, f32) {
+    let n = Complex32::new(pow as f32, 0.0);
+    let one = Complex32::new(1.0, 0.0);
+    let mut dz = one;
+    let mut iterations = 0;
+    while iterations < max_iterations && z.abs_sq() < (BAILOUT * BAILOUT) as f32 {
+        let zn1 = z.ipow(pow.max(1) - 1);
+        dz = n * zn1 * dz;
+        if !J {
+            dz += one;
+        }
+        z = zn1 * z + &c;
+        iterations += 1;
+    }
+    (iterations as IterationType, (z * dz.conjugate()).arg())
+}
+
+pub fn lighting<const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        pow,
+        mut block,
+        mut values,
+        params,
+        ..
+    } = ctx;
+    let h = height as f64;
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f64 + params.offset.1;
+        for xcoord in 0..width {
+            let x = xcoord as f64 + params.offset.0;
+            let z = Complex64::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let c = if J { params.seed } else { z };
+            let (iteration, normal) = pixel::<J>(z, c, pow, max_iterations);
+            block[ycoord - hstart][xcoord] = iteration;
+            values[ycoord - hstart][xcoord] = normal;
+        }
+    }
+    (hstart, hend)
+}
+
+pub fn lightingf32<const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        pow,
+        mut block,
+        mut values,
+        params,
+        ..
+    } = ctx;
+    let h = height as f32;
+    let seed = Complex32::new(params.seed.real as f32, params.seed.img as f32);
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f32 + params.offset.1 as f32;
+        for xcoord in 0..width {
+            let x = xcoord as f32 + params.offset.0 as f32;
+            let z = Complex32::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let c = if J { seed } else { z };
+            let (iteration, normal) = pixelf32::<J>(z, c, pow, max_iterations);
+            block[ycoord - hstart][xcoord] = iteration;
+            values[ycoord - hstart][xcoord] = normal;
+        }
+    }
+    (hstart, hend)
+}
+
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+struct Lighting<const J: bool>;
+
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+impl<const J: bool> Kernel for Lighting<J> {
+    #[inline(always)]
+    unsafe fn render<L: Lanes>(ctx: RenderContext) -> Block {
+        let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+        let RenderContext {
+            width,
+            height,
+            max_iter: max_iterations,
+            pow,
+            mut block,
+            mut values,
+            params,
+            ..
+        } = ctx;
+        let n = L::splat(L::Real::from_usize(pow as usize));
+        let one = Complex::<L>::splat(L::Real::ONE, L::Real::ZERO);
+        let bailout = L::splat(L::Real::from_f64(BAILOUT * BAILOUT));
+        let seed = Complex::<L>::splat(
+            L::Real::from_f64(params.seed.real),
+            L::Real::from_f64(params.seed.img),
+        );
+        let mut normals = [L::Real::ZERO; MAX_LANES];
+
+        for ycoord in hstart..hend {
+            if params.cancelled() {
+                return (hstart, ycoord);
+            }
+            for xcoord in (0..width).step_by(L::LANES) {
+                let lanes = L::LANES.min(width - xcoord);
+                let mut z =
+                    Complex::<L>::pixels(xcoord, ycoord, height, params.offset, (-1.8, -1.0));
+                let c = if J { seed } else { z };
+                let mut dz = one;
+                let mut active = L::first(lanes);
+                let mut iterations = L::counter(0);
+
+                for _ in 0..max_iterations {
+                    active = L::and(active, z.abs_sq().lt(bailout));
+                    if !L::any(active) {
+                        break;
+                    }
+                    iterations = L::count(iterations, active);
+
+                    // n z^(n-1), then times dz in the order of `Mul`
+                    let q = z.ipow(pow.max(1) - 1);
+                    let nq = Complex {
+                        real: n.mul(q.real),
+                        img: n.mul(q.img),
+                    };
+                    let mut next_dz = nq.cmul(dz);
+                    if !J {
+                        next_dz.real = next_dz.real.add(one.real);
+                    }
+                    let next = q.cmul(z).add(c);
+
+                    // Escaped lanes keep the point and derivative they escaped with
+                    dz = Complex::select(active, next_dz, dz);
+                    z = Complex::select(active, next, z);
+                }
+
+                L::store(
+                    iterations,
+                    &mut block[ycoord - hstart][xcoord..xcoord + lanes],
+                );
+                z.cmul(dz.conjugate()).arg().write(&mut normals[..lanes]);
+                for lane in 0..lanes {
+                    values[ycoord - hstart][xcoord + lane] = normals[lane].to_f64() as f32;
+                }
+            }
+        }
+        (hstart, hend)
+    }
+}
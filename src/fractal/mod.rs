@@ -1,35 +1,291 @@
-use pixel_canvas::Color;
-use std::thread::ScopedJoinHandle;
+use crate::formula::Formula;
+use crate::utils::complex::Complex64;
+use crate::utils::image::{Color, Image, RC};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+mod abs_variation;
+mod antialias;
+mod average;
+mod buffer;
 mod burning_ship;
+#[cfg(test)]
+mod cross_check;
+mod custom;
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+mod escape;
+mod job;
 mod julia;
+mod kernel;
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+mod lanes;
+mod lighting;
 mod mandelbrot;
+mod multibrot;
 mod newton;
+mod orbit_trap;
+mod phoenix;
+mod pool;
+mod transcendental;
+
+use buffer::FrameBuffer;
+pub use buffer::Rows;
+pub use job::RenderJob;
+use kernel::Native;
+pub use kernel::{FractalKernel, RenderContext};
+use pool::Pool;
 
 type IterationType = u32;
-type FSignature =
-    unsafe fn(usize, usize, u32, u32, usize, usize, &mut Vec<Vec<u32>>, i32, i32) -> Block;
-type Block = (usize, usize);
+type FSignature = unsafe fn(RenderContext) -> Block;
+/// Rows `start..end` of a frame
+pub type Block = (usize, usize);
+
+// Strides of the progressive passes, from the coarsest preview to the frame
+const PASSES: [usize; 4] = [8, 4, 2, 1];
+
+// Rows of a tile, the unit of work the pool hands to a thread
+const TILE_ROWS: usize = 4;
+
+/// Scene constants passed to every kernel, most kernels read only some of them
+#[derive(Clone, Debug)]
+pub struct Params {
+    /// Constant of filled-Julia renders
+    pub seed: Complex64,
+    /// Coefficient of the previous iterate in Phoenix renders
+    pub p: Complex64,
+    /// Exponent of `FractalType::Multibrot` renders
+    pub exponent: Complex64,
+    /// Formula of `FractalType::Custom` renders
+    pub formula: Option<Formula>,
+    /// Coloring the kernel was picked for, orbit-trap and average kernels
+    /// read their shape or statistic from it
+    pub color: ColorMode,
+    /// Shift of the sampled grid in pixels, every kernel samples pixel
+    /// (x, y) at (x + offset.0, y + offset.1)
+    pub offset: (f64, f64),
+    /// Set when the render job these parameters belong to is cancelled,
+    /// kernels check it before every row
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Params {
+    #[inline]
+    pub fn cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+}
 
 /// Enum for Fractal Type
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug)]
 pub enum FractalType {
     Mandelbrot,
     Julia,
     BurningShip,
     Newton,
+    AbsVariation(AbsVariant),
+    Phoenix(PhoenixForm),
+    Transcendental(Transcendental),
+    /// z^w + c for a real or complex exponent w = (re, im)
+    Multibrot(f64, f64),
+    /// A user formula, see `formula` for the language
+    Custom(Formula),
+    /// A kernel added with `Fractal::register`
+    Registered(String),
 }
 
+/// Escape-time formulas that differ from Mandelbrot only by folding
+/// (taking the absolute value of) or negating components of z
+#[derive(Clone, Debug, Copy)]
+pub enum AbsVariant {
+    /// Mandelbar: conj(z)^n + c
+    Tricorn,
+    /// |Re(z^n)| + i Im(z^n) + c
+    Celtic,
+    /// |Re(z^n)| - i Im(z^n) + c
+    CelticMandelbar,
+    /// Celtic applied over the Burning Ship folds
+    Buffalo,
+    /// conj(|Re z| + i Im z)^n + c
+    PerpendicularMandelbrot,
+    /// conj(Re z + i |Im z|)^n + c
+    PerpendicularBurningShip,
+    /// Celtic applied over the Perpendicular Mandelbrot folds
+    PerpendicularCeltic,
+    /// (|Re z| + i Im z)^n + c
+    Heart,
+}
+
+/// Phoenix formulas, iterated with the previous value of z
+#[derive(Clone, Debug, Copy)]
+pub enum PhoenixForm {
+    /// z^n + Re(c) + Im(c) z_prev
+    Classic,
+    /// z^n + c + p z_prev, with p = (re, im)
+    General(f64, f64),
+}
+
+/// Maps z -> lambda f(z) for a transcendental f, lambda is the pixel or,
+/// in Julia mode, the seed
+#[derive(Clone, Debug, Copy)]
+pub enum Transcendental {
+    Exp,
+    Sin,
+    Cos,
+    Cosh,
+}
+
+/// Shapes an orbit can be trapped by, in complex-plane coordinates
 #[derive(Clone, Copy, Debug)]
+pub enum Trap {
+    /// The point (re, im)
+    Point(f64, f64),
+    /// The line through (re, im) at an angle, in radians, to the real axis
+    Line(f64, f64, f64),
+    /// The horizontal and vertical lines through (re, im)
+    Cross(f64, f64),
+    /// The circle around (re, im) with the given radius
+    Circle(f64, f64, f64),
+}
+
+/// What the palette is indexed by
+#[derive(Clone, Copy, Debug)]
+pub enum ColorMode {
+    /// The escape iteration
+    Iteration,
+    /// The closest approach of the orbit to a trap, shaded by the iteration
+    /// it happened at. Only Mandelbrot, Julia and Burning Ship have it.
+    OrbitTrap(Trap),
+    /// An orbit statistic averaged over the iterations. Only Mandelbrot,
+    /// Julia and Burning Ship have it.
+    Average(Average),
+    /// The escape iteration, spread over the palette by its distribution
+    /// across the frame, so every palette entry covers about as many pixels.
+    /// The value in [0, 1] is how much of the linear mapping of `Iteration`
+    /// is blended in.
+    Histogram(f64),
+    /// The escape iteration, embossed by Blinn-Phong lighting of the slope
+    /// of the distance estimate. The light is at an angle in radians around
+    /// the view and a height above it, (angle, height). Only Mandelbrot and
+    /// Julia have it.
+    Lighting(f64, f64),
+}
+
+/// Anti-aliasing by averaging an N x N grid of jittered samples per pixel,
+/// in linear RGB
+#[derive(Clone, Copy, Debug)]
+pub enum Antialias {
+    /// One sample per pixel
+    Off,
+    /// Every pixel takes the grid
+    Supersample(u8),
+    /// Only pixels whose color differs from a neighbour's by more than the
+    /// threshold in some channel take the grid, (grid, threshold)
+    Adaptive(u8, u8),
+}
+
+/// Statistics of the orbit points z_n averaged by `ColorMode::Average`,
+/// each in [0, 1]
+#[derive(Clone, Copy, Debug)]
+pub enum Average {
+    /// 1/2 + 1/2 sin(density arg z_n), density sets the number of stripes
+    Stripe(f64),
+    /// Where |z_n| falls between the bounds | |z_(n-1)^p| -+ |c| | of the
+    /// triangle inequality
+    TriangleInequality,
+    /// The angle the orbit turns by at z_(n-1), over pi
+    Curvature,
+}
+
+/// Where the constant of a filled-Julia render comes from
+#[derive(Clone, Copy, Debug)]
+pub enum JuliaMode {
+    /// Render the parameter plane, the constant is the pixel itself
+    Off,
+    /// The constant follows the mouse position
+    Mouse,
+    /// The constant is fixed by the scene
+    Fixed(f64, f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Precision {
     F32,
     F64,
+    /// Mandelbrot and Julia iterate in Q4.60 fixed point, 60 fractional bits
+    /// with the same counts on every target and instruction set. The other
+    /// fractals render at `F64`.
+    Fixed,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Vector instructions the kernels use. Builds without the `simd-x86`
+/// feature, or for other targets, render every instruction set with the
+/// scalar kernels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum InstructionSet {
     None,
+    /// SSE2 kernels over 2 f64 or 4 f32 lanes for Mandelbrot, Julia,
+    /// Burning Ship, the abs variations, Phoenix and the orbit-trap, average
+    /// and lighting colorings. There is no SSE counterpart of the vector complex
+    /// functions in `complex_avx`, so the transcendental maps,
+    /// `FractalType::Multibrot` and custom formulas render with their scalar
+    /// kernels.
     SSE,
+    /// AVX2, most kernels over lanes fuse multiply-adds on CPUs with FMA,
+    /// which can move the count of a pixel near the boundary
     AVX,
+    /// Kernels over 8 f64 or 16 f32 lanes with fused multiply-adds. The
+    /// transcendental maps, `FractalType::Multibrot` and custom formulas
+    /// render with their AVX kernels.
+    AVX512,
+    /// Kernels over lanes the compiler vectorizes for any target, with the
+    /// `simd-portable` feature, for the fractals SSE has kernels for. The
+    /// others render with their scalar kernels.
+    Portable,
+}
+
+impl InstructionSet {
+    /// The widest instruction set this build has kernels for and this CPU
+    /// runs
+    pub fn detect() -> Self {
+        #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+        if is_x86_feature_detected!("avx512f") {
+            return InstructionSet::AVX512;
+        } else if is_x86_feature_detected!("avx2") {
+            return InstructionSet::AVX;
+        }
+        if cfg!(feature = "simd-portable") {
+            return InstructionSet::Portable;
+        }
+        InstructionSet::None
+    }
+
+    // This instruction set if the CPU runs it, or the widest below it that
+    // it does. AVX-512 renders some fractals with the AVX kernels, every
+    // x86-64 CPU runs SSE2, and the kernels of other targets are scalar or
+    // portable whatever the instruction set.
+    fn supported(self) -> Self {
+        #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+        match self {
+            InstructionSet::AVX512
+                if !is_x86_feature_detected!("avx512f") || !is_x86_feature_detected!("avx2") =>
+            {
+                return InstructionSet::AVX.supported();
+            }
+            InstructionSet::AVX if !is_x86_feature_detected!("avx2") => {
+                return InstructionSet::SSE;
+            }
+            _ => {}
+        }
+        self
+    }
 }
 
 pub struct Fractal {
@@ -40,10 +296,36 @@ pub struct Fractal {
     width: u16,
     height: u16,
     pow: u32,
+    julia: JuliaMode,
     iset: InstructionSet,
     precision: Precision,
-    th_block: Vec<Vec<Vec<u32>>>,
-    curr_func: FSignature,
+    color_mode: ColorMode,
+    th_block: FrameBuffer<u32>,
+    th_value: FrameBuffer<f32>,
+    curr_func: Arc<dyn FractalKernel>,
+    curr_color: ColorMode,
+    antialias: Antialias,
+    progressive: bool,
+    progress: Option<Progress>,
+    pool: Arc<Pool>,
+    kernels: HashMap<KernelKey, Arc<dyn FractalKernel>>,
+}
+
+impl Default for Fractal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Name, precision and instruction set a kernel was registered for
+type KernelKey = (String, Precision, InstructionSet);
+
+// How far a progressive frame got, and the seed it was rendered for if the
+// kernel reads one
+#[derive(Clone, Copy, PartialEq)]
+struct Progress {
+    pass: usize,
+    seed: Option<(f64, f64)>,
 }
 
 impl Fractal {
@@ -54,26 +336,48 @@ impl Fractal {
             max_iter: 100,
             width: 1280,
             pow: 2,
+            julia: JuliaMode::Off,
             color_buffer: crate::utils::color::build_color_array(100),
             height: 720,
             iset: InstructionSet::None,
             precision: Precision::F32,
-            th_block: vec![vec![vec![0; 1280]; 720]; 1],
-            curr_func: mandelbrot::mandelbrotf32,
+            color_mode: ColorMode::Iteration,
+            th_block: FrameBuffer::new(1280, 720),
+            th_value: FrameBuffer::new(1280, 720),
+            // Scalar, every CPU runs it
+            curr_func: Arc::new(unsafe { Native::new(mandelbrot::mandelbrotf32) }),
+            curr_color: ColorMode::Iteration,
+            antialias: Antialias::Off,
+            progressive: false,
+            progress: None,
+            pool: Arc::new(Pool::new(0)),
+            kernels: HashMap::new(),
         }
     }
 
+    /// Instruction set of the kernels. One this CPU does not run falls back
+    /// to the widest below it that it does.
     pub fn set_iset(mut self, iset: InstructionSet) -> Self {
-        self.iset = iset;
+        self.iset = iset.supported();
         self.set_func();
         self
     }
 
+    /// Integer exponent of the escape-time formulas, at least 1
     pub fn set_pow(mut self, pow: u32) -> Self {
-        self.pow = pow;
+        self.pow = pow.max(1);
         self
     }
 
+    /// Change the exponent of a `FractalType::Multibrot` render in place. The
+    /// kernel is kept, so this is cheap enough to call between frames.
+    pub fn set_exponent(&mut self, real: f64, img: f64) {
+        if let FractalType::Multibrot(..) = self.fractal_type {
+            self.fractal_type = FractalType::Multibrot(real, img);
+            self.progress = None;
+        }
+    }
+
     pub fn set_prec(mut self, prec: Precision) -> Self {
         self.precision = prec;
         self.set_func();
@@ -86,28 +390,147 @@ impl Fractal {
         self
     }
 
+    /// Render the filled-Julia counterpart of the current escape-time formula.
+    /// `FractalType::Julia` and `FractalType::Newton` always use the constant,
+    /// taking it from the mouse unless the mode is `Fixed`.
+    pub fn set_julia(mut self, mode: JuliaMode) -> Self {
+        self.julia = mode;
+        self.set_func();
+        self
+    }
+
+    pub fn set_antialias(mut self, antialias: Antialias) -> Self {
+        self.antialias = antialias;
+        self
+    }
+
+    /// Fractals without kernels for the mode render `ColorMode::Iteration`
+    /// instead, `get_color_mode` tells which one frames are colored by
+    pub fn set_color_mode(mut self, mode: ColorMode) -> Self {
+        self.color_mode = mode;
+        self.set_func();
+        self
+    }
+
+    /// Render every frame in passes over every 8th, 4th, 2nd and finally
+    /// every pixel, one pass per call to `draw`, and upsample the coarse ones.
+    /// A frame starts over from the coarsest pass when the seed changes and
+    /// is left alone once complete. Passes whose stride does not divide the
+    /// height are skipped, their samples would not line up with the frame.
+    pub fn set_progressive(mut self, progressive: bool) -> Self {
+        self.progressive = progressive;
+        self.progress = None;
+        self
+    }
+
     fn set_func(&mut self) {
-        self.curr_func = match self.fractal_type {
-            FractalType::BurningShip => burning_ship::fn_(self.precision, self.iset),
+        let julia = !matches!(self.julia, JuliaMode::Off);
+        self.progress = None;
+        // Only Mandelbrot, Julia and Burning Ship have orbit-trap and average
+        // kernels, and only the first two lighting kernels, other fractals
+        // fall back to iteration coloring
+        let family = match self.fractal_type {
+            FractalType::Mandelbrot => Some((false, julia)),
+            FractalType::Julia => Some((false, true)),
+            FractalType::BurningShip => Some((true, julia)),
+            _ => None,
+        };
+        let (prec, iset) = (self.precision, self.iset);
+        let kernel = match (self.color_mode, family) {
+            (ColorMode::OrbitTrap(_), Some((burning_ship, julia))) => {
+                Some(orbit_trap::fn_(burning_ship, julia, prec, iset))
+            }
+            (ColorMode::Average(average), Some((burning_ship, julia))) => {
+                Some(average::fn_(average, burning_ship, julia, prec, iset))
+            }
+            (ColorMode::Lighting(..), Some((false, julia))) => {
+                Some(lighting::fn_(julia, prec, iset))
+            }
+            _ => None,
+        };
+        if let Some(kernel) = kernel {
+            // `set_iset` only keeps instruction sets the CPU runs
+            self.curr_func = Arc::new(unsafe { Native::new(kernel) });
+            self.curr_color = self.color_mode;
+            return;
+        }
+        self.curr_color = match self.color_mode {
+            ColorMode::Histogram(_) => self.color_mode,
+            _ => ColorMode::Iteration,
+        };
+        if let FractalType::Registered(name) = &self.fractal_type {
+            let key = |iset| (name.clone(), self.precision, iset);
+            let kernel = (self.kernels.get(&key(self.iset)))
+                .or_else(|| self.kernels.get(&key(InstructionSet::None)));
+            if let Some(kernel) = kernel {
+                self.curr_func = kernel.clone();
+                return;
+            }
+        }
+        let kernel = match &self.fractal_type {
+            FractalType::BurningShip => burning_ship::fn_(julia, self.precision, self.iset),
             FractalType::Julia => julia::fn_(self.precision, self.iset),
             FractalType::Newton => newton::newton,
+            FractalType::AbsVariation(variant) => {
+                abs_variation::fn_(*variant, julia, self.precision, self.iset)
+            }
+            FractalType::Phoenix(form) => phoenix::fn_(*form, julia, self.precision, self.iset),
+            FractalType::Transcendental(map) => {
+                transcendental::fn_(*map, julia, self.precision, self.iset)
+            }
+            FractalType::Multibrot(..) => multibrot::fn_(julia, self.precision, self.iset),
+            FractalType::Custom(_) => custom::fn_(julia, self.precision, self.iset),
+            // The filled Julia set of Mandelbrot is `FractalType::Julia`
+            FractalType::Mandelbrot if julia => julia::fn_(self.precision, self.iset),
             _ => mandelbrot::fn_(self.precision, self.iset),
         };
+        // `set_iset` only keeps instruction sets the CPU runs
+        self.curr_func = Arc::new(unsafe { Native::new(kernel) });
+    }
+
+    fn params(&self, xpos: i32, ypos: i32) -> Params {
+        let seed = match self.julia {
+            JuliaMode::Fixed(real, img) => Complex64::new(real, img),
+            _ => {
+                // The point under the mouse in the parameter plane of the fractal
+                let (x0, y0) = match self.fractal_type {
+                    FractalType::BurningShip => (2.0, 0.5),
+                    _ => (1.8, 1.0),
+                };
+                let h = self.height as f64;
+                Complex64::new(xpos as f64 / h * 2.0 - x0, ypos as f64 / h * 2.0 - y0)
+            }
+        };
+        let p = match self.fractal_type {
+            FractalType::Phoenix(PhoenixForm::General(real, img)) => Complex64::new(real, img),
+            _ => Complex64::new(0.0, 0.0),
+        };
+        let exponent = match self.fractal_type {
+            FractalType::Multibrot(real, img) => Complex64::new(real, img),
+            _ => Complex64::new(self.pow as f64, 0.0),
+        };
+        let formula = match &self.fractal_type {
+            FractalType::Custom(formula) => Some(formula.clone()),
+            _ => None,
+        };
+        Params {
+            seed,
+            p,
+            exponent,
+            formula,
+            color: self.curr_color,
+            offset: (0.0, 0.0),
+            cancel: None,
+        }
     }
 
+    /// Render on `threads` threads, kept from frame to frame. Frames are cut
+    /// into tiles of a few rows which the threads take in turn, so no thread
+    /// waits on another that got the slow part of the view. At least 1.
     pub fn set_num_threads(mut self, threads: u8) -> Self {
+        let threads = threads.max(1);
         self.num_threads = Some(threads);
-        let th_height = self.height / threads as u16;
-        let th_rem = self.height % threads as u16;
-        self.th_block = Vec::new();
-        for _ in 0..threads - 1 {
-            self.th_block
-                .push(vec![vec![0; self.width as usize]; th_height as usize]);
-        }
-        self.th_block.push(vec![
-            vec![0; self.width as usize];
-            th_height as usize + th_rem as usize
-        ]);
+        self.pool = Arc::new(Pool::new(threads as usize - 1));
         self
     }
 
@@ -116,24 +539,36 @@ impl Fractal {
         (self.width, self.height)
     }
 
+    /// The coloring frames render with, the mode set unless the fractal
+    /// has no kernels for it
+    #[inline]
+    pub fn get_color_mode(&self) -> ColorMode {
+        self.curr_color
+    }
+
     pub fn set_window_dim(mut self, width: u16, height: u16) -> Self {
         self.width = width;
         self.height = height;
-        match self.num_threads {
-            None => self.th_block = vec![vec![vec![0; width as usize]; height as usize]; 1],
-            Some(threads) => {
-                let th_height = self.height / threads as u16;
-                let th_rem = self.height % threads as u16;
-                for _ in 0..threads - 1 {
-                    self.th_block
-                        .push(vec![vec![0; self.width as usize]; th_height as usize]);
-                }
-                self.th_block.push(vec![
-                    vec![0; self.width as usize];
-                    th_height as usize + th_rem as usize
-                ]);
-            }
-        };
+        self.th_block.resize(width as usize, height as usize);
+        self.th_value.resize(width as usize, height as usize);
+        self.progress = None;
+        self
+    }
+
+    /// Add a kernel rendering `FractalType::Registered(name)` at a precision
+    /// and instruction set. Without a kernel for the instruction set picked
+    /// the one registered for `InstructionSet::None` renders, and without
+    /// that the Mandelbrot set. Registered kernels are colored by iteration.
+    pub fn register(
+        mut self,
+        name: &str,
+        prec: Precision,
+        iset: InstructionSet,
+        kernel: impl FractalKernel + 'static,
+    ) -> Self {
+        self.kernels
+            .insert((name.to_string(), prec, iset), Arc::new(kernel));
+        self.set_func();
         self
     }
 
@@ -145,100 +580,407 @@ impl Fractal {
     }
 
     #[inline(always)]
-    pub fn draw(&mut self, image: &mut pixel_canvas::Image, xpos: i32, ypos: i32) {
+    pub fn draw(&mut self, image: &mut Image, xpos: i32, ypos: i32) {
+        let params = self.params(xpos, ypos);
+        if self.progressive {
+            return self.draw_progressive(image, params);
+        }
+        self.draw_full(image, params);
+    }
+
+    fn draw_full(&mut self, image: &mut Image, params: Params) {
         match self.num_threads {
-            None => self.draw_st(image, xpos, ypos),
-            Some(th) => self.draw_mt(image, th, xpos, ypos),
-        }
-    }
-
-    pub fn draw_st(&mut self, image: &mut pixel_canvas::Image, xpos: i32, ypos: i32) {
-        let func = self.curr_func;
-        let (_, _) = unsafe {
-            func(
-                0,
-                self.height as usize,
-                self.max_iter as u32,
-                self.pow,
-                self.width as usize,
-                self.height as usize,
-                &mut self.th_block[0],
-                xpos,
-                ypos,
-            )
+            None => self.draw_st(image, params),
+            Some(_) => self.draw_mt(image, params),
+        }
+    }
+
+    fn draw_progressive(&mut self, image: &mut Image, params: Params) {
+        let uses_seed = !matches!(self.julia, JuliaMode::Off)
+            || matches!(self.fractal_type, FractalType::Julia | FractalType::Newton);
+        let seed = if uses_seed {
+            Some((params.seed.real, params.seed.img))
+        } else {
+            None
+        };
+        let next = match self.progress {
+            Some(progress) if progress.seed == seed => progress.pass,
+            _ => 0,
+        };
+        let height = self.height as usize;
+        let pass = match (next..PASSES.len()).find(|&pass| height.is_multiple_of(PASSES[pass])) {
+            Some(pass) => pass,
+            None => return,
+        };
+        self.progress = Some(Progress {
+            pass: pass + 1,
+            seed,
+        });
+        match PASSES[pass] {
+            1 => self.draw_full(image, params),
+            stride => self.draw_coarse(image, stride, params),
+        }
+    }
+
+    // Render every `stride`th pixel of every `stride`th row and fill each
+    // stride x stride square with it. The kernels map pixels by the height,
+    // so the coarse frame samples the full one exactly.
+    fn draw_coarse(&mut self, image: &mut Image, stride: usize, params: Params) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (cwidth, cheight) = (width.div_ceil(stride), height / stride);
+        let mut coarse = Image::new(cwidth, cheight);
+        // Shrinking the frame buffers to the coarse frame keeps their
+        // allocation for the passes after it
+        self.th_block.resize(cwidth, cheight);
+        self.th_value.resize(cwidth, cheight);
+        let scene = Scene {
+            width: cwidth,
+            height: cheight,
+            max_iter: self.max_iter,
+            pow: self.pow,
+            func: &*self.curr_func,
+            params: &params,
+            palette: &self.color_buffer,
+            mode: self.curr_color,
+            antialias: Antialias::Off,
         };
-        for col in 0..(self.height as usize) {
-            for row in 0..self.width {
-                let idx = self.th_block[0][col][row as usize];
-                image[pixel_canvas::RC(col as usize, row as usize)] =
-                    self.color_buffer[idx as usize];
+        draw_tiled(
+            &mut coarse,
+            &self.pool,
+            &scene,
+            &mut self.th_block,
+            &mut self.th_value,
+            None,
+        );
+        self.th_block.resize(width, height);
+        self.th_value.resize(width, height);
+        for row in 0..height {
+            for col in 0..width {
+                image[RC(row, col)] = coarse[RC(row / stride, col / stride)];
             }
         }
     }
 
+    /// Start rendering one frame in the background and return at once, on
+    /// the threads of `set_num_threads`. `on_block` is called from the job's
+    /// threads with the rows of every tile and their pixels as soon as those
+    /// pixels are final. Frames drawn while the job runs wait for it.
+    pub fn render_job(
+        &self,
+        xpos: i32,
+        ypos: i32,
+        on_block: impl Fn(Block, &[Color]) + Send + Sync + 'static,
+    ) -> RenderJob {
+        RenderJob::spawn(
+            job::Frame {
+                width: self.width as usize,
+                height: self.height as usize,
+                max_iter: self.max_iter,
+                pow: self.pow,
+                pool: self.pool.clone(),
+                func: self.curr_func.clone(),
+                params: self.params(xpos, ypos),
+                palette: self.color_buffer.clone(),
+                mode: self.curr_color,
+                antialias: self.antialias,
+            },
+            on_block,
+        )
+    }
+
+    /// Render one whole frame off screen, `xpos` and `ypos` stand in for the
+    /// mouse. Progressive rendering is left out, the frame is always final.
+    pub fn render(&mut self, xpos: i32, ypos: i32) -> Image {
+        let mut image = Image::new(self.width as usize, self.height as usize);
+        let params = self.params(xpos, ypos);
+        self.draw_full(&mut image, params);
+        image
+    }
+
+    /// Render one frame off screen like `render` and return the iteration
+    /// count of every pixel instead of its colour, row by row
+    pub fn render_iterations(&mut self, xpos: i32, ypos: i32) -> Vec<u32> {
+        self.render(xpos, ypos);
+        self.th_block.rows().flatten().copied().collect()
+    }
+
+    /// Render one frame off screen and write it to `path`, as a PNG if the
+    /// path ends in `.png` and as a binary PPM otherwise. `xpos` and `ypos`
+    /// stand in for the mouse.
+    pub fn export(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        xpos: i32,
+        ypos: i32,
+    ) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let png = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+        if png && !cfg!(feature = "export-png") {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "PNG export needs the export-png feature",
+            ));
+        }
+        let image = self.render(xpos, ypos);
+        #[cfg(feature = "export-png")]
+        if png {
+            return crate::utils::png::write(path, &image);
+        }
+        crate::utils::ppm::write(path, &image)
+    }
+
+    pub fn draw_st(&mut self, image: &mut Image, params: Params) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        let func = self.curr_func.clone();
+        func.render(RenderContext {
+            rows: 0..self.height as usize,
+            width: self.width as usize,
+            height: self.height as usize,
+            max_iter: self.max_iter as u32,
+            pow: self.pow,
+            block: self.th_block.view(),
+            values: self.th_value.view(),
+            params: &params,
+        });
+        let equalized;
+        let palette = match self.curr_color {
+            ColorMode::Histogram(blend) => {
+                let histogram = histogram(&self.th_block, self.max_iter);
+                equalized = crate::utils::color::equalize(&self.color_buffer, &histogram, blend);
+                &equalized
+            }
+            _ => &self.color_buffer,
+        };
+        for row in 0..self.height as usize {
+            for col in 0..self.width as usize {
+                image[RC(row, col)] = paint(
+                    palette,
+                    self.curr_color,
+                    self.th_block[row][col],
+                    self.th_value[row][col],
+                );
+            }
+        }
+        if !matches!(self.antialias, Antialias::Off) {
+            let painted = image.to_vec();
+            let scene = Scene {
+                width: self.width as usize,
+                height: self.height as usize,
+                max_iter: self.max_iter,
+                pow: self.pow,
+                func: &*func,
+                params: &params,
+                palette,
+                mode: self.curr_color,
+                antialias: self.antialias,
+            };
+            antialias::refine(image, 0, &painted, &scene);
+        }
+    }
+
     #[inline(always)]
-    pub fn draw_mt(&mut self, image: &mut pixel_canvas::Image, thread: u8, xpos: i32, ypos: i32) {
-        draw_mt(
+    pub fn draw_mt(&mut self, image: &mut Image, params: Params) {
+        let scene = Scene {
+            width: self.width as usize,
+            height: self.height as usize,
+            max_iter: self.max_iter,
+            pow: self.pow,
+            func: &*self.curr_func,
+            params: &params,
+            palette: &self.color_buffer,
+            mode: self.curr_color,
+            antialias: self.antialias,
+        };
+        draw_tiled(
             image,
-            &self.color_buffer,
-            self.width,
-            self.height,
-            self.max_iter,
-            self.pow,
-            thread,
+            &self.pool,
+            &scene,
             &mut self.th_block,
-            xpos,
-            ypos,
-            self.curr_func,
+            &mut self.th_value,
+            None,
         );
     }
 }
 
-fn draw_mt(
-    image: &mut pixel_canvas::Image,
-    color: &Vec<Color>,
-    width: u16,
-    height: u16,
+// What the rows of a frame are rendered and painted with, borrowed from
+// the `Fractal` or job drawing it
+#[derive(Clone, Copy)]
+struct Scene<'a> {
+    width: usize,
+    height: usize,
     max_iter: u16,
     pow: u32,
-    thread: u8,
-    blocks: &mut Vec<Vec<Vec<u32>>>,
-    xpos: i32,
-    ypos: i32,
-    curr_func: FSignature,
+    func: &'a dyn FractalKernel,
+    params: &'a Params,
+    palette: &'a [Color],
+    mode: ColorMode,
+    antialias: Antialias,
+}
+
+// Told of the rows of a frame as they are rendered and as their pixels
+// become final, see `RenderJob`
+struct Listener<'a> {
+    rows: &'a AtomicUsize,
+    on_block: &'a (dyn Fn(Block, &[Color]) + Sync),
+}
+
+// Render tiles of `TILE_ROWS` rows on the pool. A cancelled frame stops
+// at the tile its kernels stopped in, and leaves the rest unpainted.
+fn draw_tiled(
+    image: &mut [Color],
+    pool: &Pool,
+    scene: &Scene,
+    blocks: &mut FrameBuffer<u32>,
+    values: &mut FrameBuffer<f32>,
+    listener: Option<&Listener>,
 ) {
-    let sthread_height: usize = (height / (thread as u16)) as usize;
-
-    let perform_op = curr_func;
-    unsafe {
-        std::thread::scope(|scope| {
-            let _ = blocks
-                .iter_mut()
-                .enumerate()
-                .map(|(i, mut c)| {
-                    scope.spawn(move || {
-                        perform_op(
-                            sthread_height * i,
-                            sthread_height * (i + 1),
-                            max_iter as u32,
-                            pow,
-                            width as usize,
-                            height as usize,
-                            &mut c,
-                            xpos,
-                            ypos,
-                        )
-                    })
-                })
-                .collect::<Vec<ScopedJoinHandle<(usize, usize)>>>();
+    let Scene {
+        width,
+        height,
+        max_iter,
+        pow,
+        func,
+        params,
+        palette,
+        mode,
+        antialias,
+    } = *scene;
+    if width == 0 || height == 0 {
+        return;
+    }
+    // Histogram palettes need every tile first, refinement its neighbours
+    let equalized = matches!(mode, ColorMode::Histogram(_));
+    let refined = !matches!(antialias, Antialias::Off);
+    let progress = |rows: usize| {
+        if let Some(listener) = listener {
+            listener.rows.fetch_add(rows, Ordering::Relaxed);
+        }
+    };
+    let publish = |hstart: usize, pixels: &[Color]| {
+        if let Some(listener) = listener {
+            (listener.on_block)((hstart, hstart + pixels.len() / width), pixels);
+        }
+    };
+    let tiles: Vec<_> = blocks
+        .tiles(TILE_ROWS)
+        .zip(values.tiles(TILE_ROWS))
+        .zip(image.chunks_mut(TILE_ROWS * width))
+        .map(Mutex::new)
+        .collect();
+    pool.run(tiles.len(), &|i| {
+        let mut tile = tiles[i].lock().unwrap();
+        let ((block, value), pixels) = &mut *tile;
+        let hstart = i * TILE_ROWS;
+        let hend = hstart + block.len();
+        let (_, done) = func.render(RenderContext {
+            rows: hstart..hend,
+            width,
+            height,
+            max_iter: max_iter as u32,
+            pow,
+            block: block.reborrow(),
+            values: value.reborrow(),
+            params,
         });
+        if done < hend {
+            return;
+        }
+        progress(hend - hstart);
+        if !equalized {
+            paint_rows(pixels, palette, mode, block, value);
+            if !refined {
+                publish(hstart, pixels);
+            }
+        }
+    });
+    drop(tiles);
+    if params.cancelled() {
+        return;
     }
-    for (i, block) in blocks.iter().enumerate() {
-        for (col, val) in block.iter().enumerate() {
-            for (row, _) in val.iter().enumerate() {
-                let idx = blocks[i][col as usize][row] as usize;
-                image[pixel_canvas::RC(sthread_height * i + col as usize, row)] = color[idx];
+
+    let equalized_palette;
+    let palette = match mode {
+        ColorMode::Histogram(blend) => {
+            let histogram = histogram(blocks, max_iter);
+            equalized_palette = crate::utils::color::equalize(palette, &histogram, blend);
+            paint_rows(
+                image,
+                &equalized_palette,
+                mode,
+                &blocks.view(),
+                &values.view(),
+            );
+            if !refined {
+                for (i, pixels) in image.chunks(TILE_ROWS * width).enumerate() {
+                    publish(i * TILE_ROWS, pixels);
+                }
             }
+            &equalized_palette
+        }
+        _ => palette,
+    };
+    if !refined {
+        return;
+    }
+
+    let painted = image.to_vec();
+    let tiles: Vec<_> = image
+        .chunks_mut(TILE_ROWS * width)
+        .map(Mutex::new)
+        .collect();
+    let scene = Scene { palette, ..*scene };
+    pool.run(tiles.len(), &|i| {
+        if params.cancelled() {
+            return;
+        }
+        let mut pixels = tiles[i].lock().unwrap();
+        antialias::refine(&mut pixels, i * TILE_ROWS, &painted, &scene);
+        progress(pixels.len() / width);
+        publish(i * TILE_ROWS, &pixels);
+    });
+}
+
+// Paint rows of iterations and values into the pixels of the same rows
+fn paint_rows(
+    pixels: &mut [Color],
+    palette: &[Color],
+    mode: ColorMode,
+    block: &Rows<u32>,
+    value: &Rows<f32>,
+) {
+    let samples = block.iter().flatten().zip(value.iter().flatten());
+    for (pixel, (&it, &val)) in pixels.iter_mut().zip(samples) {
+        *pixel = paint(palette, mode, it, val);
+    }
+}
+
+// Number of pixels at every iteration count, counts past `max_iter` from
+// registered kernels go with `max_iter` as `paint` colors them
+fn histogram(block: &FrameBuffer<u32>, max_iter: u16) -> Vec<u32> {
+    let mut histogram = vec![0; max_iter as usize + 1];
+    for row in block.rows() {
+        for &iteration in row {
+            histogram[iteration.min(max_iter as u32) as usize] += 1;
+        }
+    }
+    histogram
+}
+
+// The color stage, from the iteration and value buffers to a pixel
+#[inline(always)]
+fn paint(palette: &[Color], mode: ColorMode, iteration: u32, value: f32) -> Color {
+    match mode {
+        ColorMode::Iteration | ColorMode::Histogram(_) => {
+            palette[(iteration as usize).min(palette.len() - 1)]
+        }
+        ColorMode::OrbitTrap(_) => crate::utils::color::trap_color(palette, value, iteration),
+        ColorMode::Average(_) => crate::utils::color::average_color(palette, value, iteration),
+        ColorMode::Lighting(angle, height) => {
+            crate::utils::color::lit_color(palette, value, iteration, angle, height)
         }
     }
 }
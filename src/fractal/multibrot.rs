@@ -0,0 +1,284 @@
+use crate::utils::complex::{Complex32, Complex64};
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+use crate::utils::complex_avx::{Complex32x8, Complex64x4};
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+use core::arch::x86_64;
+
+use crate::fractal::{Block, IterationType};
+
+use super::{FSignature, InstructionSet, Precision, RenderContext};
+
+// z -> z^w + c for a real or complex exponent w, read from the scene on every
+// frame so it can change continuously. The power is taken in polar form,
+// z^w = e^(w log z) on the principal branch, which makes the cut along the
+// negative real axis visible for non-integer w; integer w agree with
+// `mandelbrot::fn_` up to rounding.
+
+pub fn fn_(julia: bool, prec: Precision, ins: InstructionSet) -> FSignature {
+    if julia {
+        kernel::<true>(prec, ins)
+    } else {
+        kernel::<false>(prec, ins)
+    }
+}
+
+// There are no SSE counterparts of the vector math, SSE runs the scalar kernel.
+fn kernel<const J: bool>(prec: Precision, ins: InstructionSet) -> FSignature {
+    match prec {
+        Precision::F64 | Precision::Fixed => match ins {
+            #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+            InstructionSet::AVX | InstructionSet::AVX512 => multibrot_simd256::<J>,
+            _ => multibrot::<J>,
+        },
+        Precision::F32 => match ins {
+            #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+            InstructionSet::AVX | InstructionSet::AVX512 => multibrotf32_simd256::<J>,
+            _ => multibrotf32::<J>,
+        },
+    }
+}
+
+#[inline(always)]
+fn pixel(mut z: Complex64, c: Complex64, w: Complex64, max_iterations: u32) -> IterationType {
+    let mut iterations = 0;
+    while iterations < max_iterations && z.abs_sq() < 4.0 {
+        z = z.powc(w);
+        z += c;
+        iterations += 1;
+    }
+    iterations as IterationType
+}
+
+#[inline(always)]
+fn pixelf32(mut z: Complex32, c: Complex32, w: Complex32, max_iterations: u32) -> IterationType {
+    let mut iterations = 0;
+    while iterations < max_iterations && z.abs_sq() < 4.0 {
+        z = z.powc(w);
+        z += c;
+        iterations += 1;
+    }
+    iterations as IterationType
+}
+
+pub fn multibrot<const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let h = height as f64;
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f64 + params.offset.1;
+        for xcoord in 0..width {
+            let x = xcoord as f64 + params.offset.0;
+            let z = Complex64::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let c = if J { params.seed } else { z };
+            block[ycoord - hstart][xcoord] = pixel(z, c, params.exponent, max_iterations);
+        }
+    }
+    (hstart, hend)
+}
+
+pub fn multibrotf32<const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let h = height as f32;
+    let seed = Complex32::new(params.seed.real as f32, params.seed.img as f32);
+    let w = Complex32::new(params.exponent.real as f32, params.exponent.img as f32);
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f32 + params.offset.1 as f32;
+        for xcoord in 0..width {
+            let x = xcoord as f32 + params.offset.0 as f32;
+            let z = Complex32::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let c = if J { seed } else { z };
+            block[ycoord - hstart][xcoord] = pixelf32(z, c, w, max_iterations);
+        }
+    }
+    (hstart, hend)
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn multibrot_simd256<const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let h = height as f64;
+    let rem = width & 3;
+    let mut iter: [i64; 4] = [0; 4];
+
+    let w = Complex64x4::new(
+        x86_64::_mm256_set1_pd(params.exponent.real),
+        x86_64::_mm256_set1_pd(params.exponent.img),
+    );
+    let compare = x86_64::_mm256_set1_pd(4.0);
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f64 + params.offset.1;
+        let py = x86_64::_mm256_set1_pd(y / h * 2.0 - 1.0);
+        for xcoord in (0..(width - rem)).step_by(4) {
+            let (x, xoff) = (xcoord as f64, params.offset.0);
+            let px = x86_64::_mm256_setr_pd(
+                (x + xoff) / h * 2.0 - 1.8,
+                (x + 1.0 + xoff) / h * 2.0 - 1.8,
+                (x + 2.0 + xoff) / h * 2.0 - 1.8,
+                (x + 3.0 + xoff) / h * 2.0 - 1.8,
+            );
+            let c = if J {
+                Complex64x4::new(
+                    x86_64::_mm256_set1_pd(params.seed.real),
+                    x86_64::_mm256_set1_pd(params.seed.img),
+                )
+            } else {
+                Complex64x4::new(px, py)
+            };
+            let mut z = Complex64x4::new(px, py);
+            let mut active = x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_EQ_OQ }>(px, px);
+            let mut iterations = x86_64::_mm256_setzero_si256();
+
+            for _ in 0..max_iterations {
+                active = x86_64::_mm256_and_pd(
+                    active,
+                    x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_LT_OQ }>(z.abs_sq(), compare),
+                );
+                if x86_64::_mm256_movemask_pd(active) == 0 {
+                    break;
+                }
+                iterations =
+                    x86_64::_mm256_sub_epi64(iterations, x86_64::_mm256_castpd_si256(active));
+
+                z = z.powc(w);
+                z = Complex64x4::new(
+                    x86_64::_mm256_add_pd(z.real, c.real),
+                    x86_64::_mm256_add_pd(z.img, c.img),
+                );
+            }
+
+            x86_64::_mm256_storeu_si256(iter.as_mut_ptr() as *mut x86_64::__m256i, iterations);
+            for (lane, count) in iter.iter().enumerate() {
+                block[ycoord - hstart][xcoord + lane] = *count as IterationType;
+            }
+        }
+        for xcoord in (width - rem)..width {
+            let x = xcoord as f64 + params.offset.0;
+            let z = Complex64::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let c = if J { params.seed } else { z };
+            block[ycoord - hstart][xcoord] = pixel(z, c, params.exponent, max_iterations);
+        }
+    }
+    (hstart, hend)
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn multibrotf32_simd256<const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let h = height as f32;
+    let rem = width & 7;
+    let seed = Complex32::new(params.seed.real as f32, params.seed.img as f32);
+    let exponent = Complex32::new(params.exponent.real as f32, params.exponent.img as f32);
+
+    let w = Complex32x8::new(
+        x86_64::_mm256_set1_ps(exponent.real),
+        x86_64::_mm256_set1_ps(exponent.img),
+    );
+    let compare = x86_64::_mm256_set1_ps(4.0);
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f32 + params.offset.1 as f32;
+        let py = x86_64::_mm256_set1_ps(y / h * 2.0 - 1.0);
+        for xcoord in (0..(width - rem)).step_by(8) {
+            let (x, xoff) = (xcoord as f32, params.offset.0 as f32);
+            let px = x86_64::_mm256_setr_ps(
+                (x + xoff) / h * 2.0 - 1.8,
+                (x + 1.0 + xoff) / h * 2.0 - 1.8,
+                (x + 2.0 + xoff) / h * 2.0 - 1.8,
+                (x + 3.0 + xoff) / h * 2.0 - 1.8,
+                (x + 4.0 + xoff) / h * 2.0 - 1.8,
+                (x + 5.0 + xoff) / h * 2.0 - 1.8,
+                (x + 6.0 + xoff) / h * 2.0 - 1.8,
+                (x + 7.0 + xoff) / h * 2.0 - 1.8,
+            );
+            let c = if J {
+                Complex32x8::new(
+                    x86_64::_mm256_set1_ps(seed.real),
+                    x86_64::_mm256_set1_ps(seed.img),
+                )
+            } else {
+                Complex32x8::new(px, py)
+            };
+            let mut z = Complex32x8::new(px, py);
+            let mut active = x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_EQ_OQ }>(px, px);
+            let mut iterations = x86_64::_mm256_setzero_si256();
+
+            for _ in 0..max_iterations {
+                active = x86_64::_mm256_and_ps(
+                    active,
+                    x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_LT_OQ }>(z.abs_sq(), compare),
+                );
+                if x86_64::_mm256_movemask_ps(active) == 0 {
+                    break;
+                }
+                iterations =
+                    x86_64::_mm256_sub_epi32(iterations, x86_64::_mm256_castps_si256(active));
+
+                z = z.powc(w);
+                z = Complex32x8::new(
+                    x86_64::_mm256_add_ps(z.real, c.real),
+                    x86_64::_mm256_add_ps(z.img, c.img),
+                );
+            }
+
+            x86_64::_mm256_storeu_si256(
+                block[ycoord - hstart][xcoord..].as_mut_ptr() as *mut x86_64::__m256i,
+                iterations,
+            );
+        }
+        for xcoord in (width - rem)..width {
+            let x = xcoord as f32 + params.offset.0 as f32;
+            let z = Complex32::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let c = if J { seed } else { z };
+            block[ycoord - hstart][xcoord] = pixelf32(z, c, exponent, max_iterations);
+        }
+    }
+    (hstart, hend)
+}
@@ -1,23 +1,21 @@
-use crate::utils::complex::{Complex32, Complex64};
-use core::arch::x86_64;
+use crate::utils::complex::Complex32;
 
 use crate::fractal::{Block, IterationType};
 
-use super::{FSignature, InstructionSet, Precision};
+use super::RenderContext;
 
-pub fn newton(
-    hstart: usize,
-    hend: usize,
-    max_iterations: u32,
-    pow: u32,
-    width: usize,
-    height: usize,
-    block: &mut Vec<Vec<u32>>,
-    xpos: i32,
-    ypos: i32,
-) -> Block {
+pub fn newton(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        mut block,
+        params,
+        ..
+    } = ctx;
     let h = height as f32;
-    let u = Complex32::new((xpos as f32) / h * 2.0 - 1.8, (ypos as f32) / h * 2.0 - 1.0);
+    let u = Complex32::new(params.seed.real as f32, params.seed.img as f32);
     let hpow = 3;
     let root = [
         Complex32::new(1.0, 0.0),
@@ -28,9 +26,12 @@ pub fn newton(
 
     let tol = 1e-6;
     for ycoord in hstart..hend {
-        let y = ycoord as f32;
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f32 + params.offset.1 as f32;
         for xcoord in 0..width {
-            let x = xcoord as f32;
+            let x = xcoord as f32 + params.offset.0 as f32;
             let mut z = Complex32::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
             let mut iterations = 0;
             while iterations < max_iterations && z.abs_sq() < 4.0 {
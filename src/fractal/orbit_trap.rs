@@ -0,0 +1,335 @@
+use crate::utils::complex::{Complex32, Complex64};
+
+use crate::fractal::{Block, IterationType};
+
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+use super::lanes::{self, Complex, Kernel, Lanes, Real, MAX_LANES};
+use super::{ColorMode, FSignature, InstructionSet, Precision, RenderContext, Trap};
+
+// Escape-time kernels that, instead of the escape iteration, record the
+// closest the orbit comes to the trap of the scene and when: the distance goes to
+// the value buffer and the iteration it happened at to the iteration
+// buffer. Every point z_1, z_2, ... produced while the orbit is bounded is
+// measured, pixels that start outside the bailout keep an infinite distance.
+//
+// `B` applies the Burning Ship folds before the power, `J` takes the
+// constant from the seed.
+
+pub fn fn_(burning_ship: bool, julia: bool, prec: Precision, ins: InstructionSet) -> FSignature {
+    match (burning_ship, julia) {
+        (false, false) => kernel::<false, false>(prec, ins),
+        (false, true) => kernel::<false, true>(prec, ins),
+        (true, false) => kernel::<true, false>(prec, ins),
+        (true, true) => kernel::<true, true>(prec, ins),
+    }
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "simd-portable",
+        all(target_arch = "x86_64", feature = "simd-x86")
+    )),
+    allow(unused_variables)
+)]
+fn kernel<const B: bool, const J: bool>(prec: Precision, ins: InstructionSet) -> FSignature {
+    #[cfg(any(
+        feature = "simd-portable",
+        all(target_arch = "x86_64", feature = "simd-x86")
+    ))]
+    if let Some(kernel) = lanes::kernel::<OrbitTrap<B, J>>(prec, ins) {
+        return kernel;
+    }
+    match prec {
+        Precision::F64 | Precision::Fixed => orbit_trap::<B, J>,
+        Precision::F32 => orbit_trapf32::<B, J>,
+    }
+}
+
+// The Burning Ship parameter plane is framed as in `burning_ship::fn_`
+#[inline(always)]
+fn origin<const B: bool, const J: bool>() -> (f64, f64) {
+    if B && !J {
+        (-2.0, -0.5)
+    } else {
+        (-1.8, -1.0)
+    }
+}
+
+// Distances are taken without `hypot` and, for f32, in f32, the way the
+// vector kernels take them, so that both pick the same closest point
+#[inline(always)]
+fn distance(trap: Trap, zx: f64, zy: f64) -> f64 {
+    match trap {
+        Trap::Point(cx, cy) => ((zx - cx) * (zx - cx) + (zy - cy) * (zy - cy)).sqrt(),
+        Trap::Line(cx, cy, angle) => {
+            let (sin, cos) = angle.sin_cos();
+            ((zx - cx) * sin - (zy - cy) * cos).abs()
+        }
+        Trap::Cross(cx, cy) => (zx - cx).abs().min((zy - cy).abs()),
+        Trap::Circle(cx, cy, radius) => {
+            (((zx - cx) * (zx - cx) + (zy - cy) * (zy - cy)).sqrt() - radius).abs()
+        }
+    }
+}
+
+#[inline(always)]
+fn distancef32(trap: Trap, zx: f32, zy: f32) -> f32 {
+    let (cx, cy) = match trap {
+        Trap::Point(cx, cy) | Trap::Line(cx, cy, _) | Trap::Cross(cx, cy) => (cx, cy),
+        Trap::Circle(cx, cy, _) => (cx, cy),
+    };
+    let (dx, dy) = (zx - cx as f32, zy - cy as f32);
+    match trap {
+        Trap::Point(..) => (dx * dx + dy * dy).sqrt(),
+        Trap::Line(_, _, angle) => {
+            let (sin, cos) = angle.sin_cos();
+            (dx * sin as f32 - dy * cos as f32).abs()
+        }
+        Trap::Cross(..) => dx.abs().min(dy.abs()),
+        Trap::Circle(_, _, radius) => ((dx * dx + dy * dy).sqrt() - radius as f32).abs(),
+    }
+}
+
+#[inline(always)]
+fn pixel<const B: bool>(
+    mut z: Complex64,
+    c: Complex64,
+    trap: Trap,
+    pow: u32,
+    max_iterations: u32,
+) -> (IterationType, f32) {
+    let mut best = f64::INFINITY;
+    let mut best_iteration = 0;
+    let mut iterations = 0;
+    while iterations < max_iterations && z.abs_sq() < 4.0 {
+        if B {
+            z.real = -z.real.abs();
+            z.img = z.img.abs();
+        }
+        z = z.ipow(pow);
+        z += c;
+        iterations += 1;
+        let d = distance(trap, z.real, z.img);
+        if d < best {
+            best = d;
+            best_iteration = iterations;
+        }
+    }
+    (best_iteration as IterationType, best as f32)
+}
+
+#[inline(always)]
+fn pixelf32<const B: bool>(
+    mut z: Complex32,
+    c: Complex32,
+    trap: Trap,
+    pow: u32,
+    max_iterations: u32,
+) -> (IterationType, f32) {
+    let mut best = f32::INFINITY;
+    let mut best_iteration = 0;
+    let mut iterations = 0;
+    while iterations < max_iterations && z.abs_sq() < 4.0 {
+        if B {
+            z.real = -z.real.abs();
+            z.img = z.img.abs();
+        }
+        z = z.ipow(pow);
+        z += c;
+        iterations += 1;
+        let d = distancef32(trap, z.real, z.img);
+        if d < best {
+            best = d;
+            best_iteration = iterations;
+        }
+    }
+    (best_iteration as IterationType, best)
+}
+
+pub fn orbit_trap<const B: bool, const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        pow,
+        mut block,
+        mut values,
+        params,
+        ..
+    } = ctx;
+    let trap = match params.color {
+        ColorMode::OrbitTrap(trap) => trap,
+        _ => return (hstart, hend),
+    };
+    let h = height as f64;
+    let (ox, oy) = origin::<B, J>();
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f64 + params.offset.1;
+        for xcoord in 0..width {
+            let x = xcoord as f64 + params.offset.0;
+            let z = Complex64::new(x / h * 2.0 + ox, y / h * 2.0 + oy);
+            let c = if J { params.seed } else { z };
+            let (iteration, d) = pixel::<B>(z, c, trap, pow, max_iterations);
+            block[ycoord - hstart][xcoord] = iteration;
+            values[ycoord - hstart][xcoord] = d;
+        }
+    }
+    (hstart, hend)
+}
+
+pub fn orbit_trapf32<const B: bool, const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        pow,
+        mut block,
+        mut values,
+        params,
+        ..
+    } = ctx;
+    let trap = match params.color {
+        ColorMode::OrbitTrap(trap) => trap,
+        _ => return (hstart, hend),
+    };
+    let h = height as f32;
+    let (ox, oy) = origin::<B, J>();
+    let (ox, oy) = (ox as f32, oy as f32);
+    let seed = Complex32::new(params.seed.real as f32, params.seed.img as f32);
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f32 + params.offset.1 as f32;
+        for xcoord in 0..width {
+            let x = xcoord as f32 + params.offset.0 as f32;
+            let z = Complex32::new(x / h * 2.0 + ox, y / h * 2.0 + oy);
+            let c = if J { seed } else { z };
+            let (iteration, d) = pixelf32::<B>(z, c, trap, pow, max_iterations);
+            block[ycoord - hstart][xcoord] = iteration;
+            values[ycoord - hstart][xcoord] = d;
+        }
+    }
+    (hstart, hend)
+}
+
+// Distances as `distance` and `distancef32` take them
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+#[inline(always)]
+unsafe fn distance_lanes<L: Lanes>(trap: Trap, z: Complex<L>) -> L {
+    let real = |value: f64| L::splat(L::Real::from_f64(value));
+    let (cx, cy) = match trap {
+        Trap::Point(cx, cy) | Trap::Line(cx, cy, _) | Trap::Cross(cx, cy) => (cx, cy),
+        Trap::Circle(cx, cy, _) => (cx, cy),
+    };
+    let (dx, dy) = (z.real.sub(real(cx)), z.img.sub(real(cy)));
+    let hypot = || dx.mul_add(dx, dy.mul(dy)).sqrt();
+    match trap {
+        Trap::Point(..) => hypot(),
+        Trap::Line(_, _, angle) => {
+            let (sin, cos) = angle.sin_cos();
+            dx.mul_sub(real(sin), dy.mul(real(cos))).abs()
+        }
+        Trap::Cross(..) => dx.abs().min(dy.abs()),
+        Trap::Circle(_, _, radius) => hypot().sub(real(radius)).abs(),
+    }
+}
+
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+struct OrbitTrap<const B: bool, const J: bool>;
+
+// The closest distance and the iteration it was reached at are kept in
+// lanes, updated only for lanes still active
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+impl<const B: bool, const J: bool> Kernel for OrbitTrap<B, J> {
+    #[inline(always)]
+    unsafe fn render<L: Lanes>(ctx: RenderContext) -> Block {
+        let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+        let RenderContext {
+            width,
+            height,
+            max_iter: max_iterations,
+            pow,
+            mut block,
+            mut values,
+            params,
+            ..
+        } = ctx;
+        let trap = match params.color {
+            ColorMode::OrbitTrap(trap) => trap,
+            _ => return (hstart, hend),
+        };
+        let four = L::splat(L::Real::from_f64(4.0));
+        let seed = Complex::<L>::splat(
+            L::Real::from_f64(params.seed.real),
+            L::Real::from_f64(params.seed.img),
+        );
+        let mut bests = [L::Real::ZERO; MAX_LANES];
+        let mut best_iterations = [L::Real::ZERO; MAX_LANES];
+
+        for ycoord in hstart..hend {
+            if params.cancelled() {
+                return (hstart, ycoord);
+            }
+            for xcoord in (0..width).step_by(L::LANES) {
+                let lanes = L::LANES.min(width - xcoord);
+                let mut z =
+                    Complex::<L>::pixels(xcoord, ycoord, height, params.offset, origin::<B, J>());
+                let c = if J { seed } else { z };
+                let mut active = L::first(lanes);
+                let mut best = L::splat(L::Real::from_f64(f64::INFINITY));
+                let mut best_iteration = L::splat(L::Real::ZERO);
+
+                for i in 0..max_iterations {
+                    active = L::and(active, z.abs_sq().lt(four));
+                    if !L::any(active) {
+                        break;
+                    }
+
+                    if B {
+                        z.real = z.real.abs().neg();
+                        z.img = z.img.abs();
+                    }
+                    z = z.ipow(pow).add(c);
+
+                    let d = distance_lanes(trap, z);
+                    let closer = L::and(active, d.lt(best));
+                    best = L::select(closer, d, best);
+                    best_iteration = L::select(
+                        closer,
+                        L::splat(L::Real::from_usize(i as usize + 1)),
+                        best_iteration,
+                    );
+                }
+
+                best.write(&mut bests[..lanes]);
+                best_iteration.write(&mut best_iterations[..lanes]);
+                for lane in 0..lanes {
+                    block[ycoord - hstart][xcoord + lane] =
+                        best_iterations[lane].to_f64() as IterationType;
+                    values[ycoord - hstart][xcoord + lane] = bests[lane].to_f64() as f32;
+                }
+            }
+        }
+        (hstart, hend)
+    }
+}
@@ -0,0 +1,125 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+// `Pool::run` erases the lifetime of its task, it holds until the call
+// returns, and the call returns only after every index it handed out has
+type Task = &'static (dyn Fn(usize) + Sync);
+
+/// Worker threads kept across frames. Every call to `run` queues the indices
+/// of one frame's tiles, workers pull them in order from the queue until it
+/// is empty, so a worker done with a cheap tile goes on with the next one.
+/// The fractal and its background jobs share the pool, a call made while
+/// another frame is queued waits for that frame to end.
+pub struct Pool {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    // Signalled when a frame is queued or the pool shuts down
+    work: Condvar,
+    // Signalled when the last running index of a frame returns, and when
+    // the frame leaves the queue
+    done: Condvar,
+}
+
+#[derive(Default)]
+struct Queue {
+    task: Option<Task>,
+    next: usize,
+    count: usize,
+    running: usize,
+    panicked: bool,
+    quit: bool,
+}
+
+impl Pool {
+    /// Spawn `workers` threads, the thread calling `run` works as well
+    pub fn new(workers: usize) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            work: Condvar::new(),
+            done: Condvar::new(),
+        });
+        let workers = (0..workers)
+            .map(|_| {
+                let shared = shared.clone();
+                std::thread::spawn(move || work(&shared))
+            })
+            .collect();
+        Pool { shared, workers }
+    }
+
+    /// Call `task` with every index below `count` and return once all calls
+    /// have. Panics after the frame if one of the calls did.
+    pub fn run(&self, count: usize, task: &(dyn Fn(usize) + Sync)) {
+        let task: Task = unsafe { std::mem::transmute(task) };
+        let mut queue = self.shared.queue.lock().unwrap();
+        while queue.task.is_some() {
+            queue = self.shared.done.wait(queue).unwrap();
+        }
+        queue.task = Some(task);
+        queue.next = 0;
+        queue.count = count;
+        self.shared.work.notify_all();
+        loop {
+            if queue.next < queue.count {
+                queue = self.shared.step(queue);
+            } else if queue.running > 0 {
+                queue = self.shared.done.wait(queue).unwrap();
+            } else {
+                break;
+            }
+        }
+        queue.task = None;
+        self.shared.done.notify_all();
+        if std::mem::take(&mut queue.panicked) {
+            drop(queue);
+            panic!("a tile of the frame panicked");
+        }
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().quit = true;
+        self.shared.work.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Shared {
+    // Run the next index of the queued frame, unlocked while it runs
+    fn step<'a>(&'a self, mut queue: MutexGuard<'a, Queue>) -> MutexGuard<'a, Queue> {
+        let (task, index) = match queue.task {
+            Some(task) if queue.next < queue.count => (task, queue.next),
+            _ => return queue,
+        };
+        queue.next += 1;
+        queue.running += 1;
+        drop(queue);
+        let ok = panic::catch_unwind(AssertUnwindSafe(|| task(index))).is_ok();
+        let mut queue = self.queue.lock().unwrap();
+        queue.running -= 1;
+        queue.panicked |= !ok;
+        if queue.running == 0 && queue.next == queue.count {
+            self.done.notify_all();
+        }
+        queue
+    }
+}
+
+fn work(shared: &Shared) {
+    let mut queue = shared.queue.lock().unwrap();
+    while !queue.quit {
+        if queue.task.is_some() && queue.next < queue.count {
+            queue = shared.step(queue);
+        } else {
+            queue = shared.work.wait(queue).unwrap();
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use crate::utils::complex::{Complex32, Complex64};
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+use crate::utils::complex_avx::{Complex32x8, Complex64x4};
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+use core::arch::x86_64;
+
+use crate::fractal::{Block, IterationType};
+
+use super::{FSignature, InstructionSet, Precision, RenderContext, Transcendental};
+
+// Every map is z -> lambda * f(z). Orbits of these maps escape along one
+// direction only, so instead of |z| < 2 each map is bounded by the
+// component that blows up: Re z for exp, |Im z| for sin and cos, |Re z|
+// for cosh.
+const EXP: u8 = 0;
+const SIN: u8 = 1;
+const COS: u8 = 2;
+const COSH: u8 = 3;
+
+const BAILOUT: f64 = 50.0;
+
+pub fn fn_(map: Transcendental, julia: bool, prec: Precision, ins: InstructionSet) -> FSignature {
+    match map {
+        Transcendental::Exp => kernel::<EXP>(julia, prec, ins),
+        Transcendental::Sin => kernel::<SIN>(julia, prec, ins),
+        Transcendental::Cos => kernel::<COS>(julia, prec, ins),
+        Transcendental::Cosh => kernel::<COSH>(julia, prec, ins),
+    }
+}
+
+// The parameter plane starts from z = lambda, the first iterate of the
+// critical (or, for exp, asymptotic) value that all four maps send to 1.
+fn kernel<const F: u8>(julia: bool, prec: Precision, ins: InstructionSet) -> FSignature {
+    if julia {
+        kernel_for::<F, true>(prec, ins)
+    } else {
+        kernel_for::<F, false>(prec, ins)
+    }
+}
+
+// There are no SSE counterparts of the vector math, SSE runs the scalar kernel.
+fn kernel_for<const F: u8, const J: bool>(prec: Precision, ins: InstructionSet) -> FSignature {
+    match prec {
+        Precision::F64 | Precision::Fixed => match ins {
+            #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+            InstructionSet::AVX | InstructionSet::AVX512 => transcendental_simd256::<F, J>,
+            _ => transcendental::<F, J>,
+        },
+        Precision::F32 => match ins {
+            #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+            InstructionSet::AVX | InstructionSet::AVX512 => transcendentalf32_simd256::<F, J>,
+            _ => transcendentalf32::<F, J>,
+        },
+    }
+}
+
+#[inline(always)]
+fn pixel<const F: u8>(mut z: Complex64, lambda: Complex64, max_iterations: u32) -> IterationType {
+    let mut iterations = 0;
+    while iterations < max_iterations && bounded::<F>(z.real, z.img) {
+        let fz = match F {
+            EXP => z.exp(),
+            SIN => z.sin(),
+            COS => z.cos(),
+            _ => z.cosh(),
+        };
+        z = lambda * fz;
+        iterations += 1;
+    }
+    iterations as IterationType
+}
+
+#[inline(always)]
+fn pixelf32<const F: u8>(
+    mut z: Complex32,
+    lambda: Complex32,
+    max_iterations: u32,
+) -> IterationType {
+    let mut iterations = 0;
+    while iterations < max_iterations && bounded::<F>(z.real as f64, z.img as f64) {
+        let fz = match F {
+            EXP => z.exp(),
+            SIN => z.sin(),
+            COS => z.cos(),
+            _ => z.cosh(),
+        };
+        z = lambda * fz;
+        iterations += 1;
+    }
+    iterations as IterationType
+}
+
+#[inline(always)]
+fn bounded<const F: u8>(real: f64, img: f64) -> bool {
+    match F {
+        EXP => real < BAILOUT,
+        SIN | COS => img.abs() < BAILOUT,
+        _ => real.abs() < BAILOUT,
+    }
+}
+
+pub fn transcendental<const F: u8, const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let h = height as f64;
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f64 + params.offset.1;
+        for xcoord in 0..width {
+            let x = xcoord as f64 + params.offset.0;
+            let z = Complex64::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let lambda = if J { params.seed } else { z };
+            block[ycoord - hstart][xcoord] = pixel::<F>(z, lambda, max_iterations);
+        }
+    }
+    (hstart, hend)
+}
+
+pub fn transcendentalf32<const F: u8, const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let h = height as f32;
+    let seed = Complex32::new(params.seed.real as f32, params.seed.img as f32);
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f32 + params.offset.1 as f32;
+        for xcoord in 0..width {
+            let x = xcoord as f32 + params.offset.0 as f32;
+            let z = Complex32::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let lambda = if J { seed } else { z };
+            block[ycoord - hstart][xcoord] = pixelf32::<F>(z, lambda, max_iterations);
+        }
+    }
+    (hstart, hend)
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn transcendental_simd256<const F: u8, const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let h = height as f64;
+    let rem = width & 3;
+    let mut iter: [i64; 4] = [0; 4];
+
+    let sign = x86_64::_mm256_set1_pd(-0.0);
+    let bailout = x86_64::_mm256_set1_pd(BAILOUT);
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f64 + params.offset.1;
+        let py = x86_64::_mm256_set1_pd(y / h * 2.0 - 1.0);
+        for xcoord in (0..(width - rem)).step_by(4) {
+            let (x, xoff) = (xcoord as f64, params.offset.0);
+            let px = x86_64::_mm256_setr_pd(
+                (x + xoff) / h * 2.0 - 1.8,
+                (x + 1.0 + xoff) / h * 2.0 - 1.8,
+                (x + 2.0 + xoff) / h * 2.0 - 1.8,
+                (x + 3.0 + xoff) / h * 2.0 - 1.8,
+            );
+            let lambda = if J {
+                Complex64x4::new(
+                    x86_64::_mm256_set1_pd(params.seed.real),
+                    x86_64::_mm256_set1_pd(params.seed.img),
+                )
+            } else {
+                Complex64x4::new(px, py)
+            };
+            let mut z = Complex64x4::new(px, py);
+            let mut active = x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_EQ_OQ }>(px, px);
+            let mut iterations = x86_64::_mm256_setzero_si256();
+
+            for _ in 0..max_iterations {
+                let component = match F {
+                    EXP => z.real,
+                    SIN | COS => x86_64::_mm256_andnot_pd(sign, z.img),
+                    _ => x86_64::_mm256_andnot_pd(sign, z.real),
+                };
+                active = x86_64::_mm256_and_pd(
+                    active,
+                    x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_LT_OQ }>(component, bailout),
+                );
+                if x86_64::_mm256_movemask_pd(active) == 0 {
+                    break;
+                }
+                iterations =
+                    x86_64::_mm256_sub_epi64(iterations, x86_64::_mm256_castpd_si256(active));
+
+                let fz = match F {
+                    EXP => z.exp(),
+                    SIN => z.sin(),
+                    COS => z.cos(),
+                    _ => z.cosh(),
+                };
+                z = lambda.cmul(fz);
+            }
+
+            x86_64::_mm256_storeu_si256(iter.as_mut_ptr() as *mut x86_64::__m256i, iterations);
+            for (lane, count) in iter.iter().enumerate() {
+                block[ycoord - hstart][xcoord + lane] = *count as IterationType;
+            }
+        }
+        for xcoord in (width - rem)..width {
+            let x = xcoord as f64 + params.offset.0;
+            let z = Complex64::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let lambda = if J { params.seed } else { z };
+            block[ycoord - hstart][xcoord] = pixel::<F>(z, lambda, max_iterations);
+        }
+    }
+    (hstart, hend)
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn transcendentalf32_simd256<const F: u8, const J: bool>(ctx: RenderContext) -> Block {
+    let (hstart, hend) = (ctx.rows.start, ctx.rows.end);
+    let RenderContext {
+        width,
+        height,
+        max_iter: max_iterations,
+        mut block,
+        params,
+        ..
+    } = ctx;
+    let h = height as f32;
+    let rem = width & 7;
+    let seed = Complex32::new(params.seed.real as f32, params.seed.img as f32);
+
+    let sign = x86_64::_mm256_set1_ps(-0.0);
+    let bailout = x86_64::_mm256_set1_ps(BAILOUT as f32);
+
+    for ycoord in hstart..hend {
+        if params.cancelled() {
+            return (hstart, ycoord);
+        }
+        let y = ycoord as f32 + params.offset.1 as f32;
+        let py = x86_64::_mm256_set1_ps(y / h * 2.0 - 1.0);
+        for xcoord in (0..(width - rem)).step_by(8) {
+            let (x, xoff) = (xcoord as f32, params.offset.0 as f32);
+            let px = x86_64::_mm256_setr_ps(
+                (x + xoff) / h * 2.0 - 1.8,
+                (x + 1.0 + xoff) / h * 2.0 - 1.8,
+                (x + 2.0 + xoff) / h * 2.0 - 1.8,
+                (x + 3.0 + xoff) / h * 2.0 - 1.8,
+                (x + 4.0 + xoff) / h * 2.0 - 1.8,
+                (x + 5.0 + xoff) / h * 2.0 - 1.8,
+                (x + 6.0 + xoff) / h * 2.0 - 1.8,
+                (x + 7.0 + xoff) / h * 2.0 - 1.8,
+            );
+            let lambda = if J {
+                Complex32x8::new(
+                    x86_64::_mm256_set1_ps(seed.real),
+                    x86_64::_mm256_set1_ps(seed.img),
+                )
+            } else {
+                Complex32x8::new(px, py)
+            };
+            let mut z = Complex32x8::new(px, py);
+            let mut active = x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_EQ_OQ }>(px, px);
+            let mut iterations = x86_64::_mm256_setzero_si256();
+
+            for _ in 0..max_iterations {
+                let component = match F {
+                    EXP => z.real,
+                    SIN | COS => x86_64::_mm256_andnot_ps(sign, z.img),
+                    _ => x86_64::_mm256_andnot_ps(sign, z.real),
+                };
+                active = x86_64::_mm256_and_ps(
+                    active,
+                    x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_LT_OQ }>(component, bailout),
+                );
+                if x86_64::_mm256_movemask_ps(active) == 0 {
+                    break;
+                }
+                iterations =
+                    x86_64::_mm256_sub_epi32(iterations, x86_64::_mm256_castps_si256(active));
+
+                let fz = match F {
+                    EXP => z.exp(),
+                    SIN => z.sin(),
+                    COS => z.cos(),
+                    _ => z.cosh(),
+                };
+                z = lambda.cmul(fz);
+            }
+
+            x86_64::_mm256_storeu_si256(
+                block[ycoord - hstart][xcoord..].as_mut_ptr() as *mut x86_64::__m256i,
+                iterations,
+            );
+        }
+        for xcoord in (width - rem)..width {
+            let x = xcoord as f32 + params.offset.0 as f32;
+            let z = Complex32::new(x / h * 2.0 - 1.8, y / h * 2.0 - 1.0);
+            let lambda = if J { seed } else { z };
+            block[ycoord - hstart][xcoord] = pixelf32::<F>(z, lambda, max_iterations);
+        }
+    }
+    (hstart, hend)
+}
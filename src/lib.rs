@@ -0,0 +1,18 @@
+//! Escape-time fractal renderer. Build a `Fractal`, pick what and how to
+//! render with its setters, then render frames with `Fractal::render`, in
+//! the background with `Fractal::render_job`, or into a window with the
+//! `viewer` binary. Everything the crate root exports is the public API,
+//! the modules behind it are free to change.
+mod formula;
+mod fractal;
+// mod polynomial;
+mod utils;
+
+pub use formula::{Formula, ParseError};
+pub use fractal::{
+    AbsVariant, Antialias, Average, Block, ColorMode, Fractal, FractalKernel, FractalType,
+    InstructionSet, JuliaMode, Params, PhoenixForm, Precision, RenderContext, RenderJob, Rows,
+    Transcendental, Trap,
+};
+pub use utils::complex::{Complex32, Complex64};
+pub use utils::image::{Color, Image, RC};
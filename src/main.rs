@@ -1,9 +1,5 @@
-#![feature(stdsimd)]
-use fractal::Fractal;
+use fractals::{Fractal, FractalType, InstructionSet, Precision};
 use pixel_canvas::{input::MouseState, Canvas};
-mod fractal;
-// mod polynomial;
-mod utils;
 
 fn main() {
     // The canvas will render for you at up to 60fps.
@@ -11,23 +7,40 @@ fn main() {
         .set_window_dim(1600, 900)
         .set_num_threads(16)
         .set_max_iter(127)
-        .set_prec(fractal::Precision::F32)
-        .set_iset(fractal::InstructionSet::AVX)
-        .set_fractal(fractal::FractalType::Julia)
+        .set_prec(Precision::F32)
+        .set_iset(InstructionSet::detect())
+        .set_fractal(FractalType::Julia)
         .set_pow(2);
 
     let (width, height) = fractal.get_dim();
 
+    // Render a single frame without opening a window, the centre of the
+    // view stands in for the mouse
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|arg| arg == "--export") {
+        let path = args.get(i + 1).map_or("fractal.ppm", String::as_str);
+        if let Err(err) = fractal.export(path, width as i32 / 2, height as i32 / 2) {
+            eprintln!("{}: {}", path, err);
+        }
+        return;
+    }
+
     let canvas = Canvas::new(width as usize, height as usize)
         .title("Fractal")
         .show_ms(true)
         .state(MouseState::new())
         .input(MouseState::handle_input);
 
+    // Progressive frames build on the last one, so it is kept across
+    // redraws and copied to the window every time
+    let mut frame = fractals::Image::new(width as usize, height as usize);
     canvas.render(move |mouse, image| {
         // to do: zoom in
         let x = mouse.x;
         let y = mouse.y;
-        fractal.draw(image, x, y);
+        fractal.draw(&mut frame, x, y);
+        for (pixel, color) in image.iter_mut().zip(frame.iter()) {
+            *pixel = pixel_canvas::Color::rgb(color.r, color.g, color.b);
+        }
     });
 }
@@ -1,5 +1,5 @@
+use crate::utils::image::Color;
 use colorsys::{Hsl, Rgb};
-use pixel_canvas::Color;
 
 pub fn build_color_array(max_iter: u32) -> Vec<Color> {
     let mut color_array: Vec<Color> = Vec::new();
@@ -8,7 +8,7 @@ pub fn build_color_array(max_iter: u32) -> Vec<Color> {
         let i = iter as f64;
         let fr = i / fmx;
         let intensity: f32 = fr.sqrt() as f32;
-        let quad_intensity: f32 = intensity.sqrt() as f32;
+        let quad_intensity: f32 = intensity.sqrt();
         let deg: f32 = 270_f32;
         let hue: f32 = quad_intensity * 100.0;
         let rgb: Rgb = Rgb::from(Hsl::from((deg - 12.0_f32, hue, quad_intensity * 80.0)));
@@ -20,3 +20,131 @@ pub fn build_color_array(max_iter: u32) -> Vec<Color> {
     }
     color_array
 }
+
+/// How fast the palette fades with the distance of an orbit to its trap
+const TRAP_FALLOFF: f32 = 8.0;
+
+/// Color of an orbit-trap pixel. The distance of the closest approach picks
+/// the palette entry, near misses taking the top of the palette, and the
+/// iteration it happened at darkens it, late approaches down to half.
+pub fn trap_color(palette: &[Color], distance: f32, iteration: u32) -> Color {
+    let max_iter = palette.len() - 1;
+    let t = (-TRAP_FALLOFF * distance).exp();
+    let color = palette[(t * max_iter as f32) as usize];
+    let shade = 1.0 - 0.5 * (iteration as f32 / max_iter as f32).min(1.0);
+    Color {
+        r: (color.r as f32 * shade) as u8,
+        g: (color.g as f32 * shade) as u8,
+        b: (color.b as f32 * shade) as u8,
+    }
+}
+
+/// Color of an average-colored pixel, the average in [0, 1] spans the
+/// palette. Bounded orbits take the top of the palette, as they do when
+/// coloring by iteration.
+pub fn average_color(palette: &[Color], average: f32, iteration: u32) -> Color {
+    let max_iter = palette.len() - 1;
+    if iteration as usize >= max_iter {
+        return palette[max_iter];
+    }
+    palette[(average.clamp(0.0, 1.0) * max_iter as f32) as usize]
+}
+
+/// Palette indexed by iteration that spreads the escaped iterations of a
+/// frame evenly over `palette`: every iteration takes the entry at the share
+/// of escaped pixels below it. `blend` mixes in the linear mapping, bounded
+/// orbits keep the last entry.
+pub fn equalize(palette: &[Color], histogram: &[u32], blend: f64) -> Vec<Color> {
+    let max_iter = palette.len() - 1;
+    let escaped: u64 = histogram[..max_iter].iter().map(|&n| n as u64).sum();
+    let mut below = 0;
+    let mut equalized = Vec::with_capacity(palette.len());
+    for (iteration, &count) in histogram[..max_iter].iter().enumerate() {
+        let rank = if escaped > 0 {
+            below as f64 / escaped as f64
+        } else {
+            0.0
+        };
+        let linear = iteration as f64 / max_iter as f64;
+        let t = (1.0 - blend) * rank + blend * linear;
+        equalized.push(palette[(t.clamp(0.0, 1.0) * max_iter as f64) as usize]);
+        below += count as u64;
+    }
+    equalized.push(palette[max_iter]);
+    equalized
+}
+
+const AMBIENT: f64 = 0.2;
+const DIFFUSE: f64 = 0.8;
+const SPECULAR: f64 = 0.4;
+const SHININESS: i32 = 20;
+
+/// Color of a lit pixel, the palette color at `iteration` under Blinn-Phong
+/// lighting. The surface leans 45 degrees towards `normal`, an angle in the
+/// plane, the light comes from `light_angle` at `light_height` above the
+/// plane and the viewer looks straight down. Bounded orbits and normals
+/// lost to overflow are left flat.
+pub fn lit_color(
+    palette: &[Color],
+    normal: f32,
+    iteration: u32,
+    light_angle: f64,
+    light_height: f64,
+) -> Color {
+    let color = palette[iteration as usize];
+    if iteration as usize >= palette.len() - 1 {
+        return color;
+    }
+    let n = if normal.is_finite() {
+        let (sin, cos) = (normal as f64).sin_cos();
+        [cos, sin, 1.0]
+    } else {
+        [0.0, 0.0, 1.0]
+    };
+    let (sin, cos) = light_angle.sin_cos();
+    let light = [cos, sin, light_height];
+    let half = {
+        let l = normalize(light);
+        [l[0], l[1], l[2] + 1.0]
+    };
+    let diffuse = dot(normalize(n), normalize(light)).max(0.0);
+    let specular = dot(normalize(n), normalize(half)).max(0.0).powi(SHININESS);
+    let lit = |channel: u8| {
+        (channel as f64 * (AMBIENT + DIFFUSE * diffuse) + 255.0 * SPECULAR * specular).min(255.0)
+            as u8
+    };
+    Color {
+        r: lit(color.r),
+        g: lit(color.g),
+        b: lit(color.b),
+    }
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(a: [f64; 3]) -> [f64; 3] {
+    let length = dot(a, a).sqrt();
+    [a[0] / length, a[1] / length, a[2] / length]
+}
+
+/// An sRGB channel as linear light in [0, 1]
+pub fn to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Linear light in [0, 1] as an sRGB channel
+pub fn from_linear(light: f32) -> u8 {
+    let c = if light <= 0.0031308 {
+        light * 12.92
+    } else {
+        1.055 * light.powf(1.0 / 2.4) - 0.055
+    };
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
@@ -198,6 +198,79 @@ macro_rules! impl_complex_for {
                     img: -self.img,
                 }
             }
+
+            #[inline(always)]
+            pub fn abs(self) -> $type {
+                self.real.hypot(self.img)
+            }
+
+            /// Principal argument, in (-pi, pi]
+            #[inline(always)]
+            pub fn arg(self) -> $type {
+                self.img.atan2(self.real)
+            }
+
+            pub fn exp(self) -> Self {
+                let r = self.real.exp();
+                let (sin, cos) = self.img.sin_cos();
+                Self {
+                    real: r * cos,
+                    img: r * sin,
+                }
+            }
+
+            /// Principal logarithm, the branch cut lies along the negative real axis
+            pub fn log(self) -> Self {
+                Self {
+                    real: self.abs().ln(),
+                    img: self.arg(),
+                }
+            }
+
+            /// Principal power e^(w log z), with 0^w taken as 0. A negative
+            /// zero imaginary part is read as +0, so the whole negative real
+            /// axis lands on arg = pi instead of flipping between -pi and pi
+            pub fn powc(self, w: Self) -> Self {
+                if self.real == 0.0 && self.img == 0.0 {
+                    return Self {
+                        real: 0.0,
+                        img: 0.0,
+                    };
+                }
+                (w * Self::new(self.real, self.img + 0.0).log()).exp()
+            }
+
+            pub fn sin(self) -> Self {
+                let (sin, cos) = self.real.sin_cos();
+                Self {
+                    real: sin * self.img.cosh(),
+                    img: cos * self.img.sinh(),
+                }
+            }
+
+            pub fn cos(self) -> Self {
+                let (sin, cos) = self.real.sin_cos();
+                Self {
+                    real: cos * self.img.cosh(),
+                    img: -sin * self.img.sinh(),
+                }
+            }
+
+            pub fn sinh(self) -> Self {
+                let (sin, cos) = self.img.sin_cos();
+                Self {
+                    real: self.real.sinh() * cos,
+                    img: self.real.cosh() * sin,
+                }
+            }
+
+            pub fn cosh(self) -> Self {
+                let (sin, cos) = self.img.sin_cos();
+                Self {
+                    real: self.real.cosh() * cos,
+                    img: self.real.sinh() * sin,
+                }
+            }
         }
     };
 }
@@ -205,3 +278,38 @@ macro_rules! impl_complex_for {
 
 impl_complex_for!(Complex64, f64);
 impl_complex_for!(Complex32, f32);
+
+/// `z^power` of a complex number kept as its two parts, multiplied out with
+/// `cmul` in the order of `Complex64::ipow`. The vector kernels raise their
+/// lanes with it, so with a `cmul` that rounds like `Mul` they count like
+/// the scalar kernels for every power.
+#[cfg(any(
+    feature = "simd-portable",
+    all(target_arch = "x86_64", feature = "simd-x86")
+))]
+#[inline(always)]
+pub fn ipow_with<T: Copy>(
+    z: (T, T),
+    mut power: u32,
+    one: (T, T),
+    cmul: impl Fn((T, T), (T, T)) -> (T, T),
+) -> (T, T) {
+    match power {
+        0 => one,
+        1 => z,
+        2 => cmul(z, z),
+        3 => cmul(cmul(z, z), z),
+        _ => {
+            let mut result = one;
+            let mut mul = z;
+            while power > 0 {
+                if power & 1 == 1 {
+                    result = cmul(result, mul);
+                }
+                mul = cmul(mul, mul);
+                power >>= 1;
+            }
+            result
+        }
+    }
+}
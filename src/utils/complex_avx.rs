@@ -0,0 +1,1024 @@
+// AVX counterparts of `Complex64` and `Complex32`, holding one complex
+// number per lane, together with the lane-wise exp, log, sin/cos and atan2
+// they are built on.
+//
+// The real functions reduce the argument to a small interval and evaluate
+// a truncated series there. They are accurate to a few ulp for arguments of
+// moderate size (|x| below ~1e5 for sin/cos), which is all the escape-time
+// kernels need. Past its range exp is infinite or 0 as in libm, but none of
+// them take infinities, NaNs or denormals, and the complex log and powc
+// need |z|^2 to be finite.
+use core::arch::x86_64;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Complex64x4 {
+    pub real: x86_64::__m256d,
+    pub img: x86_64::__m256d,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Complex32x8 {
+    pub real: x86_64::__m256,
+    pub img: x86_64::__m256,
+}
+
+const FRAC_2_PI: f64 = std::f64::consts::FRAC_2_PI;
+// pi/2 split so that k * PIO2_HI is exact for the k we see in practice
+const PIO2_HI: f64 = 1.5707963267341256;
+const PIO2_LO: f64 = 6.077100506506192e-11;
+const LN2_HI: f64 = 6.931471803691238e-1;
+const LN2_LO: f64 = 1.9082149292705877e-10;
+// ln of the largest finite and of the smallest normal double and float
+const EXP_MAX: f64 = 709.782712893384;
+const EXP_MIN: f64 = -708.3964185322641;
+const EXPF_MAX: f32 = 88.72284;
+const EXPF_MIN: f32 = -87.33655;
+// Adding this rounds a small double to an integer held in the low mantissa bits
+const ROUND_MAGIC: f64 = 6755399441055744.0;
+
+#[inline(always)]
+unsafe fn horner_pd(x: x86_64::__m256d, coeff: &[f64]) -> x86_64::__m256d {
+    let mut acc = x86_64::_mm256_set1_pd(coeff[0]);
+    for c in &coeff[1..] {
+        acc = x86_64::_mm256_add_pd(x86_64::_mm256_mul_pd(acc, x), x86_64::_mm256_set1_pd(*c));
+    }
+    acc
+}
+
+#[inline(always)]
+unsafe fn horner_ps(x: x86_64::__m256, coeff: &[f32]) -> x86_64::__m256 {
+    let mut acc = x86_64::_mm256_set1_ps(coeff[0]);
+    for c in &coeff[1..] {
+        acc = x86_64::_mm256_add_ps(x86_64::_mm256_mul_ps(acc, x), x86_64::_mm256_set1_ps(*c));
+    }
+    acc
+}
+
+// Integer-valued doubles (|k| < 2^51) to i64 lanes
+#[inline(always)]
+unsafe fn to_epi64(k: x86_64::__m256d) -> x86_64::__m256i {
+    let magic = x86_64::_mm256_set1_pd(ROUND_MAGIC);
+    x86_64::_mm256_sub_epi64(
+        x86_64::_mm256_castpd_si256(x86_64::_mm256_add_pd(k, magic)),
+        x86_64::_mm256_castpd_si256(magic),
+    )
+}
+
+// 2^k for integer-valued doubles with a normal result
+#[inline(always)]
+unsafe fn pow2_pd(k: x86_64::__m256d) -> x86_64::__m256d {
+    x86_64::_mm256_castsi256_pd(x86_64::_mm256_slli_epi64::<52>(x86_64::_mm256_add_epi64(
+        to_epi64(k),
+        x86_64::_mm256_set1_epi64x(1023),
+    )))
+}
+
+// 2^k for i32 lanes with a normal result
+#[inline(always)]
+unsafe fn pow2_ps(k: x86_64::__m256i) -> x86_64::__m256 {
+    x86_64::_mm256_castsi256_ps(x86_64::_mm256_slli_epi32::<23>(x86_64::_mm256_add_epi32(
+        k,
+        x86_64::_mm256_set1_epi32(127),
+    )))
+}
+
+/// e^x in every lane, infinite past `EXP_MAX` and 0 below `EXP_MIN`
+#[inline]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn exp_pd(x: x86_64::__m256d) -> x86_64::__m256d {
+    let high = x86_64::_mm256_set1_pd(EXP_MAX);
+    let low = x86_64::_mm256_set1_pd(EXP_MIN);
+    let overflow = x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_GT_OQ }>(x, high);
+    let underflow = x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_LT_OQ }>(x, low);
+    let x = x86_64::_mm256_min_pd(x86_64::_mm256_max_pd(x, low), high);
+    let k = x86_64::_mm256_round_pd::<
+        { x86_64::_MM_FROUND_TO_NEAREST_INT | x86_64::_MM_FROUND_NO_EXC },
+    >(x86_64::_mm256_mul_pd(
+        x,
+        x86_64::_mm256_set1_pd(std::f64::consts::LOG2_E),
+    ));
+    let r = x86_64::_mm256_sub_pd(
+        x86_64::_mm256_sub_pd(x, x86_64::_mm256_mul_pd(k, x86_64::_mm256_set1_pd(LN2_HI))),
+        x86_64::_mm256_mul_pd(k, x86_64::_mm256_set1_pd(LN2_LO)),
+    );
+    // Taylor series of e^r, |r| <= ln(2) / 2
+    let p = horner_pd(
+        r,
+        &[
+            1.0 / 479001600.0,
+            1.0 / 39916800.0,
+            1.0 / 3628800.0,
+            1.0 / 362880.0,
+            1.0 / 40320.0,
+            1.0 / 5040.0,
+            1.0 / 720.0,
+            1.0 / 120.0,
+            1.0 / 24.0,
+            1.0 / 6.0,
+            0.5,
+            1.0,
+            1.0,
+        ],
+    );
+    // 2^k in two halves, at the ends of the range 2^k alone is not normal
+    let k1 = x86_64::_mm256_floor_pd(x86_64::_mm256_mul_pd(k, x86_64::_mm256_set1_pd(0.5)));
+    let k2 = x86_64::_mm256_sub_pd(k, k1);
+    let e = x86_64::_mm256_mul_pd(x86_64::_mm256_mul_pd(p, pow2_pd(k1)), pow2_pd(k2));
+    let e = x86_64::_mm256_blendv_pd(e, x86_64::_mm256_set1_pd(f64::INFINITY), overflow);
+    x86_64::_mm256_andnot_pd(underflow, e)
+}
+
+/// Natural logarithm of positive, normal lanes, -inf at 0
+#[inline]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn log_pd(x: x86_64::__m256d) -> x86_64::__m256d {
+    let bits = x86_64::_mm256_castpd_si256(x);
+    // Biased exponent as a double, through the same magic as `to_epi64`
+    let two52 = x86_64::_mm256_set1_pd(4503599627370496.0);
+    let mut e = x86_64::_mm256_sub_pd(
+        x86_64::_mm256_castsi256_pd(x86_64::_mm256_or_si256(
+            x86_64::_mm256_srli_epi64::<52>(bits),
+            x86_64::_mm256_castpd_si256(two52),
+        )),
+        two52,
+    );
+    e = x86_64::_mm256_sub_pd(e, x86_64::_mm256_set1_pd(1023.0));
+    // Mantissa in [1, 2), moved to [sqrt(1/2), sqrt(2))
+    let mut m = x86_64::_mm256_castsi256_pd(x86_64::_mm256_or_si256(
+        x86_64::_mm256_and_si256(bits, x86_64::_mm256_set1_epi64x(0x000F_FFFF_FFFF_FFFF)),
+        x86_64::_mm256_set1_epi64x(0x3FF0_0000_0000_0000),
+    ));
+    let big = x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_GT_OQ }>(
+        m,
+        x86_64::_mm256_set1_pd(std::f64::consts::SQRT_2),
+    );
+    m = x86_64::_mm256_blendv_pd(
+        m,
+        x86_64::_mm256_mul_pd(m, x86_64::_mm256_set1_pd(0.5)),
+        big,
+    );
+    e = x86_64::_mm256_add_pd(e, x86_64::_mm256_and_pd(big, x86_64::_mm256_set1_pd(1.0)));
+
+    // log(m) = 2 atanh(s), |s| <= 0.172
+    let one = x86_64::_mm256_set1_pd(1.0);
+    let s = x86_64::_mm256_div_pd(x86_64::_mm256_sub_pd(m, one), x86_64::_mm256_add_pd(m, one));
+    let s2 = x86_64::_mm256_mul_pd(s, s);
+    let p = horner_pd(
+        s2,
+        &[
+            2.0 / 21.0,
+            2.0 / 19.0,
+            2.0 / 17.0,
+            2.0 / 15.0,
+            2.0 / 13.0,
+            2.0 / 11.0,
+            2.0 / 9.0,
+            2.0 / 7.0,
+            2.0 / 5.0,
+            2.0 / 3.0,
+            2.0,
+        ],
+    );
+    let log = x86_64::_mm256_add_pd(
+        x86_64::_mm256_mul_pd(s, p),
+        x86_64::_mm256_mul_pd(e, x86_64::_mm256_set1_pd(std::f64::consts::LN_2)),
+    );
+    x86_64::_mm256_blendv_pd(
+        log,
+        x86_64::_mm256_set1_pd(f64::NEG_INFINITY),
+        x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_EQ_OQ }>(x, x86_64::_mm256_setzero_pd()),
+    )
+}
+
+/// (sin x, cos x) in every lane
+#[inline]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn sin_cos_pd(x: x86_64::__m256d) -> (x86_64::__m256d, x86_64::__m256d) {
+    let k = x86_64::_mm256_round_pd::<
+        { x86_64::_MM_FROUND_TO_NEAREST_INT | x86_64::_MM_FROUND_NO_EXC },
+    >(x86_64::_mm256_mul_pd(x, x86_64::_mm256_set1_pd(FRAC_2_PI)));
+    let r = x86_64::_mm256_sub_pd(
+        x86_64::_mm256_sub_pd(x, x86_64::_mm256_mul_pd(k, x86_64::_mm256_set1_pd(PIO2_HI))),
+        x86_64::_mm256_mul_pd(k, x86_64::_mm256_set1_pd(PIO2_LO)),
+    );
+    let r2 = x86_64::_mm256_mul_pd(r, r);
+    // Taylor series on |r| <= pi/4
+    let s = x86_64::_mm256_mul_pd(
+        r,
+        horner_pd(
+            r2,
+            &[
+                -1.0 / 1307674368000.0,
+                1.0 / 6227020800.0,
+                -1.0 / 39916800.0,
+                1.0 / 362880.0,
+                -1.0 / 5040.0,
+                1.0 / 120.0,
+                -1.0 / 6.0,
+                1.0,
+            ],
+        ),
+    );
+    let c = horner_pd(
+        r2,
+        &[
+            1.0 / 20922789888000.0,
+            -1.0 / 87178291200.0,
+            1.0 / 479001600.0,
+            -1.0 / 3628800.0,
+            1.0 / 40320.0,
+            -1.0 / 720.0,
+            1.0 / 24.0,
+            -0.5,
+            1.0,
+        ],
+    );
+
+    // Quadrant k mod 4 picks and signs the two series
+    let q = to_epi64(k);
+    let one = x86_64::_mm256_set1_epi64x(1);
+    let swap = x86_64::_mm256_castsi256_pd(x86_64::_mm256_cmpeq_epi64(
+        x86_64::_mm256_and_si256(q, one),
+        one,
+    ));
+    let two = x86_64::_mm256_set1_epi64x(2);
+    let sin_sign = x86_64::_mm256_slli_epi64::<62>(x86_64::_mm256_and_si256(q, two));
+    let cos_sign = x86_64::_mm256_slli_epi64::<62>(x86_64::_mm256_and_si256(
+        x86_64::_mm256_add_epi64(q, one),
+        two,
+    ));
+    let sin = x86_64::_mm256_blendv_pd(s, c, swap);
+    let cos = x86_64::_mm256_blendv_pd(c, s, swap);
+    (
+        x86_64::_mm256_xor_pd(sin, x86_64::_mm256_castsi256_pd(sin_sign)),
+        x86_64::_mm256_xor_pd(cos, x86_64::_mm256_castsi256_pd(cos_sign)),
+    )
+}
+
+/// Four-quadrant arctangent of y / x in every lane, in [-pi, pi]
+#[inline]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn atan2_pd(y: x86_64::__m256d, x: x86_64::__m256d) -> x86_64::__m256d {
+    let sign = x86_64::_mm256_set1_pd(-0.0);
+    let ay = x86_64::_mm256_andnot_pd(sign, y);
+    let ax = x86_64::_mm256_andnot_pd(sign, x);
+    let hi = x86_64::_mm256_max_pd(ay, ax);
+    let lo = x86_64::_mm256_min_pd(ay, ax);
+    let zero = x86_64::_mm256_setzero_pd();
+    // t in [0, 1], left at 0 for atan2(0, 0)
+    let t = x86_64::_mm256_blendv_pd(
+        x86_64::_mm256_div_pd(lo, hi),
+        zero,
+        x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_EQ_OQ }>(hi, zero),
+    );
+    // atan(t) = pi/6 + atan((t sqrt3 - 1) / (t + sqrt3)) brings |t| under tan(pi/12)
+    let sqrt3 = x86_64::_mm256_set1_pd(1.7320508075688772);
+    let reduce = x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_GT_OQ }>(
+        t,
+        x86_64::_mm256_set1_pd(0.2679491924311227),
+    );
+    let t = x86_64::_mm256_blendv_pd(
+        t,
+        x86_64::_mm256_div_pd(
+            x86_64::_mm256_sub_pd(x86_64::_mm256_mul_pd(t, sqrt3), x86_64::_mm256_set1_pd(1.0)),
+            x86_64::_mm256_add_pd(t, sqrt3),
+        ),
+        reduce,
+    );
+    let t2 = x86_64::_mm256_mul_pd(t, t);
+    let mut r = x86_64::_mm256_mul_pd(
+        t,
+        horner_pd(
+            t2,
+            &[
+                1.0 / 29.0,
+                -1.0 / 27.0,
+                1.0 / 25.0,
+                -1.0 / 23.0,
+                1.0 / 21.0,
+                -1.0 / 19.0,
+                1.0 / 17.0,
+                -1.0 / 15.0,
+                1.0 / 13.0,
+                -1.0 / 11.0,
+                1.0 / 9.0,
+                -1.0 / 7.0,
+                1.0 / 5.0,
+                -1.0 / 3.0,
+                1.0,
+            ],
+        ),
+    );
+    r = x86_64::_mm256_add_pd(
+        r,
+        x86_64::_mm256_and_pd(reduce, x86_64::_mm256_set1_pd(std::f64::consts::FRAC_PI_6)),
+    );
+
+    // Undo the octant folding
+    r = x86_64::_mm256_blendv_pd(
+        r,
+        x86_64::_mm256_sub_pd(x86_64::_mm256_set1_pd(std::f64::consts::FRAC_PI_2), r),
+        x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_GT_OQ }>(ay, ax),
+    );
+    // blendv reads the sign bit, so x itself selects the left half-plane
+    r = x86_64::_mm256_blendv_pd(
+        r,
+        x86_64::_mm256_sub_pd(x86_64::_mm256_set1_pd(std::f64::consts::PI), r),
+        x,
+    );
+    x86_64::_mm256_or_pd(r, x86_64::_mm256_and_pd(sign, y))
+}
+
+/// e^x in every lane, infinite past `EXPF_MAX` and 0 below `EXPF_MIN`
+#[inline]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn exp_ps(x: x86_64::__m256) -> x86_64::__m256 {
+    let high = x86_64::_mm256_set1_ps(EXPF_MAX);
+    let low = x86_64::_mm256_set1_ps(EXPF_MIN);
+    let overflow = x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_GT_OQ }>(x, high);
+    let underflow = x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_LT_OQ }>(x, low);
+    let x = x86_64::_mm256_min_ps(x86_64::_mm256_max_ps(x, low), high);
+    let k = x86_64::_mm256_cvtps_epi32(x86_64::_mm256_mul_ps(
+        x,
+        x86_64::_mm256_set1_ps(std::f32::consts::LOG2_E),
+    ));
+    let kf = x86_64::_mm256_cvtepi32_ps(k);
+    let r = x86_64::_mm256_sub_ps(
+        x86_64::_mm256_sub_ps(
+            x,
+            x86_64::_mm256_mul_ps(kf, x86_64::_mm256_set1_ps(0.6933594)),
+        ),
+        x86_64::_mm256_mul_ps(kf, x86_64::_mm256_set1_ps(-2.1219444e-4)),
+    );
+    let p = horner_ps(
+        r,
+        &[
+            1.0 / 5040.0,
+            1.0 / 720.0,
+            1.0 / 120.0,
+            1.0 / 24.0,
+            1.0 / 6.0,
+            0.5,
+            1.0,
+            1.0,
+        ],
+    );
+    // 2^k in two halves, at the ends of the range 2^k alone is not normal
+    let k1 = x86_64::_mm256_srai_epi32::<1>(k);
+    let k2 = x86_64::_mm256_sub_epi32(k, k1);
+    let e = x86_64::_mm256_mul_ps(x86_64::_mm256_mul_ps(p, pow2_ps(k1)), pow2_ps(k2));
+    let e = x86_64::_mm256_blendv_ps(e, x86_64::_mm256_set1_ps(f32::INFINITY), overflow);
+    x86_64::_mm256_andnot_ps(underflow, e)
+}
+
+/// Natural logarithm of positive, normal lanes, -inf at 0
+#[inline]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn log_ps(x: x86_64::__m256) -> x86_64::__m256 {
+    let bits = x86_64::_mm256_castps_si256(x);
+    let mut e = x86_64::_mm256_cvtepi32_ps(x86_64::_mm256_sub_epi32(
+        x86_64::_mm256_srli_epi32::<23>(bits),
+        x86_64::_mm256_set1_epi32(127),
+    ));
+    let mut m = x86_64::_mm256_castsi256_ps(x86_64::_mm256_or_si256(
+        x86_64::_mm256_and_si256(bits, x86_64::_mm256_set1_epi32(0x007F_FFFF)),
+        x86_64::_mm256_set1_epi32(0x3F80_0000),
+    ));
+    let big = x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_GT_OQ }>(
+        m,
+        x86_64::_mm256_set1_ps(std::f32::consts::SQRT_2),
+    );
+    m = x86_64::_mm256_blendv_ps(
+        m,
+        x86_64::_mm256_mul_ps(m, x86_64::_mm256_set1_ps(0.5)),
+        big,
+    );
+    e = x86_64::_mm256_add_ps(e, x86_64::_mm256_and_ps(big, x86_64::_mm256_set1_ps(1.0)));
+
+    let one = x86_64::_mm256_set1_ps(1.0);
+    let s = x86_64::_mm256_div_ps(x86_64::_mm256_sub_ps(m, one), x86_64::_mm256_add_ps(m, one));
+    let s2 = x86_64::_mm256_mul_ps(s, s);
+    let p = horner_ps(
+        s2,
+        &[2.0 / 11.0, 2.0 / 9.0, 2.0 / 7.0, 2.0 / 5.0, 2.0 / 3.0, 2.0],
+    );
+    let log = x86_64::_mm256_add_ps(
+        x86_64::_mm256_mul_ps(s, p),
+        x86_64::_mm256_mul_ps(e, x86_64::_mm256_set1_ps(std::f32::consts::LN_2)),
+    );
+    x86_64::_mm256_blendv_ps(
+        log,
+        x86_64::_mm256_set1_ps(f32::NEG_INFINITY),
+        x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_EQ_OQ }>(x, x86_64::_mm256_setzero_ps()),
+    )
+}
+
+/// (sin x, cos x) in every lane
+#[inline]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn sin_cos_ps(x: x86_64::__m256) -> (x86_64::__m256, x86_64::__m256) {
+    let q = x86_64::_mm256_cvtps_epi32(x86_64::_mm256_mul_ps(
+        x,
+        x86_64::_mm256_set1_ps(std::f32::consts::FRAC_2_PI),
+    ));
+    let k = x86_64::_mm256_cvtepi32_ps(q);
+    // pi/2 in three parts, the first two exact in f32
+    let r = x86_64::_mm256_sub_ps(
+        x86_64::_mm256_sub_ps(
+            x86_64::_mm256_sub_ps(
+                x,
+                x86_64::_mm256_mul_ps(k, x86_64::_mm256_set1_ps(1.5703125)),
+            ),
+            x86_64::_mm256_mul_ps(k, x86_64::_mm256_set1_ps(4.837513e-4)),
+        ),
+        x86_64::_mm256_mul_ps(k, x86_64::_mm256_set1_ps(7.54979e-8)),
+    );
+    let r2 = x86_64::_mm256_mul_ps(r, r);
+    let s = x86_64::_mm256_mul_ps(
+        r,
+        horner_ps(
+            r2,
+            &[1.0 / 362880.0, -1.0 / 5040.0, 1.0 / 120.0, -1.0 / 6.0, 1.0],
+        ),
+    );
+    let c = horner_ps(
+        r2,
+        &[
+            -1.0 / 3628800.0,
+            1.0 / 40320.0,
+            -1.0 / 720.0,
+            1.0 / 24.0,
+            -0.5,
+            1.0,
+        ],
+    );
+
+    let one = x86_64::_mm256_set1_epi32(1);
+    let swap = x86_64::_mm256_castsi256_ps(x86_64::_mm256_cmpeq_epi32(
+        x86_64::_mm256_and_si256(q, one),
+        one,
+    ));
+    let two = x86_64::_mm256_set1_epi32(2);
+    let sin_sign = x86_64::_mm256_slli_epi32::<30>(x86_64::_mm256_and_si256(q, two));
+    let cos_sign = x86_64::_mm256_slli_epi32::<30>(x86_64::_mm256_and_si256(
+        x86_64::_mm256_add_epi32(q, one),
+        two,
+    ));
+    let sin = x86_64::_mm256_blendv_ps(s, c, swap);
+    let cos = x86_64::_mm256_blendv_ps(c, s, swap);
+    (
+        x86_64::_mm256_xor_ps(sin, x86_64::_mm256_castsi256_ps(sin_sign)),
+        x86_64::_mm256_xor_ps(cos, x86_64::_mm256_castsi256_ps(cos_sign)),
+    )
+}
+
+/// Four-quadrant arctangent of y / x in every lane, in [-pi, pi]
+#[inline]
+#[target_feature(enable = "avx,avx2")]
+pub unsafe fn atan2_ps(y: x86_64::__m256, x: x86_64::__m256) -> x86_64::__m256 {
+    let sign = x86_64::_mm256_set1_ps(-0.0);
+    let ay = x86_64::_mm256_andnot_ps(sign, y);
+    let ax = x86_64::_mm256_andnot_ps(sign, x);
+    let hi = x86_64::_mm256_max_ps(ay, ax);
+    let lo = x86_64::_mm256_min_ps(ay, ax);
+    let zero = x86_64::_mm256_setzero_ps();
+    let t = x86_64::_mm256_blendv_ps(
+        x86_64::_mm256_div_ps(lo, hi),
+        zero,
+        x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_EQ_OQ }>(hi, zero),
+    );
+    let sqrt3 = x86_64::_mm256_set1_ps(1.7320508);
+    let reduce =
+        x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_GT_OQ }>(t, x86_64::_mm256_set1_ps(0.2679492));
+    let t = x86_64::_mm256_blendv_ps(
+        t,
+        x86_64::_mm256_div_ps(
+            x86_64::_mm256_sub_ps(x86_64::_mm256_mul_ps(t, sqrt3), x86_64::_mm256_set1_ps(1.0)),
+            x86_64::_mm256_add_ps(t, sqrt3),
+        ),
+        reduce,
+    );
+    let t2 = x86_64::_mm256_mul_ps(t, t);
+    let mut r = x86_64::_mm256_mul_ps(
+        t,
+        horner_ps(
+            t2,
+            &[
+                -1.0 / 15.0,
+                1.0 / 13.0,
+                -1.0 / 11.0,
+                1.0 / 9.0,
+                -1.0 / 7.0,
+                1.0 / 5.0,
+                -1.0 / 3.0,
+                1.0,
+            ],
+        ),
+    );
+    r = x86_64::_mm256_add_ps(
+        r,
+        x86_64::_mm256_and_ps(reduce, x86_64::_mm256_set1_ps(std::f32::consts::FRAC_PI_6)),
+    );
+
+    r = x86_64::_mm256_blendv_ps(
+        r,
+        x86_64::_mm256_sub_ps(x86_64::_mm256_set1_ps(std::f32::consts::FRAC_PI_2), r),
+        x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_GT_OQ }>(ay, ax),
+    );
+    r = x86_64::_mm256_blendv_ps(
+        r,
+        x86_64::_mm256_sub_ps(x86_64::_mm256_set1_ps(std::f32::consts::PI), r),
+        x,
+    );
+    x86_64::_mm256_or_ps(r, x86_64::_mm256_and_ps(sign, y))
+}
+
+// Resolves to the `_mm256_<op>_pd` / `_mm256_<op>_ps` intrinsic, or to the
+// helper of that width
+macro_rules! paste_op {
+    (pd, add) => {
+        x86_64::_mm256_add_pd
+    };
+    (pd, sub) => {
+        x86_64::_mm256_sub_pd
+    };
+    (pd, mul) => {
+        x86_64::_mm256_mul_pd
+    };
+    (pd, div) => {
+        x86_64::_mm256_div_pd
+    };
+    (pd, set1) => {
+        x86_64::_mm256_set1_pd
+    };
+    (pd, setzero) => {
+        x86_64::_mm256_setzero_pd
+    };
+    (pd, andnot) => {
+        x86_64::_mm256_andnot_pd
+    };
+    (pd, sqrt) => {
+        x86_64::_mm256_sqrt_pd
+    };
+    (pd, xor) => {
+        x86_64::_mm256_xor_pd
+    };
+    (pd, cmpeq) => {
+        x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_EQ_OQ }>
+    };
+    (pd, cmplt) => {
+        x86_64::_mm256_cmp_pd::<{ x86_64::_CMP_LT_OQ }>
+    };
+    (pd, blendv) => {
+        x86_64::_mm256_blendv_pd
+    };
+    (pd, horner) => {
+        horner_pd
+    };
+    (pd, loadu) => {
+        x86_64::_mm256_loadu_pd
+    };
+    (pd, storeu) => {
+        x86_64::_mm256_storeu_pd
+    };
+    (ps, add) => {
+        x86_64::_mm256_add_ps
+    };
+    (ps, sub) => {
+        x86_64::_mm256_sub_ps
+    };
+    (ps, mul) => {
+        x86_64::_mm256_mul_ps
+    };
+    (ps, div) => {
+        x86_64::_mm256_div_ps
+    };
+    (ps, set1) => {
+        x86_64::_mm256_set1_ps
+    };
+    (ps, setzero) => {
+        x86_64::_mm256_setzero_ps
+    };
+    (ps, andnot) => {
+        x86_64::_mm256_andnot_ps
+    };
+    (ps, sqrt) => {
+        x86_64::_mm256_sqrt_ps
+    };
+    (ps, xor) => {
+        x86_64::_mm256_xor_ps
+    };
+    (ps, cmpeq) => {
+        x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_EQ_OQ }>
+    };
+    (ps, cmplt) => {
+        x86_64::_mm256_cmp_ps::<{ x86_64::_CMP_LT_OQ }>
+    };
+    (ps, blendv) => {
+        x86_64::_mm256_blendv_ps
+    };
+    (ps, horner) => {
+        horner_ps
+    };
+    (ps, loadu) => {
+        x86_64::_mm256_loadu_ps
+    };
+    (ps, storeu) => {
+        x86_64::_mm256_storeu_ps
+    };
+}
+
+macro_rules! impl_complex_avx_for {
+    (
+        $class:ident,
+        $vec:ty,
+        $scalar:ty,
+        $suffix:ident,
+        $exp:ident,
+        $log:ident,
+        $sin_cos:ident,
+        $atan2:ident
+    ) => {
+        impl $class {
+            #[inline(always)]
+            pub fn new(real: $vec, img: $vec) -> Self {
+                Self { real, img }
+            }
+
+            /// The same number in every lane
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn splat(real: $scalar, img: $scalar) -> Self {
+                Self {
+                    real: paste_op!($suffix, set1)(real),
+                    img: paste_op!($suffix, set1)(img),
+                }
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn add(self, b: Self) -> Self {
+                Self {
+                    real: paste_op!($suffix, add)(self.real, b.real),
+                    img: paste_op!($suffix, add)(self.img, b.img),
+                }
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn sub(self, b: Self) -> Self {
+                Self {
+                    real: paste_op!($suffix, sub)(self.real, b.real),
+                    img: paste_op!($suffix, sub)(self.img, b.img),
+                }
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn neg(self) -> Self {
+                let sign = paste_op!($suffix, set1)(-0.0);
+                Self {
+                    real: paste_op!($suffix, xor)(self.real, sign),
+                    img: paste_op!($suffix, xor)(self.img, sign),
+                }
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn conjugate(self) -> Self {
+                Self {
+                    real: self.real,
+                    img: paste_op!($suffix, xor)(self.img, paste_op!($suffix, set1)(-0.0)),
+                }
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn cdiv(self, b: Self) -> Self {
+                let abs = b.abs_sq();
+                Self {
+                    real: paste_op!($suffix, div)(
+                        paste_op!($suffix, add)(
+                            paste_op!($suffix, mul)(self.real, b.real),
+                            paste_op!($suffix, mul)(self.img, b.img),
+                        ),
+                        abs,
+                    ),
+                    img: paste_op!($suffix, div)(
+                        paste_op!($suffix, sub)(
+                            paste_op!($suffix, mul)(self.img, b.real),
+                            paste_op!($suffix, mul)(self.real, b.img),
+                        ),
+                        abs,
+                    ),
+                }
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn abs(self) -> $vec {
+                paste_op!($suffix, sqrt)(self.abs_sq())
+            }
+
+            /// Principal argument, see `Complex64::arg`
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn arg(self) -> $vec {
+                $atan2(self.img, self.real)
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn abs_sq(self) -> $vec {
+                paste_op!($suffix, add)(
+                    paste_op!($suffix, mul)(self.real, self.real),
+                    paste_op!($suffix, mul)(self.img, self.img),
+                )
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn cmul(self, b: Self) -> Self {
+                Self {
+                    real: paste_op!($suffix, sub)(
+                        paste_op!($suffix, mul)(self.real, b.real),
+                        paste_op!($suffix, mul)(self.img, b.img),
+                    ),
+                    img: paste_op!($suffix, add)(
+                        paste_op!($suffix, mul)(self.img, b.real),
+                        paste_op!($suffix, mul)(self.real, b.img),
+                    ),
+                }
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn exp(self) -> Self {
+                let r = $exp(self.real);
+                let (sin, cos) = $sin_cos(self.img);
+                Self {
+                    real: paste_op!($suffix, mul)(r, cos),
+                    img: paste_op!($suffix, mul)(r, sin),
+                }
+            }
+
+            /// Principal logarithm, see `Complex64::log`
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn log(self) -> Self {
+                Self {
+                    real: paste_op!($suffix, mul)(
+                        $log(self.abs_sq()),
+                        paste_op!($suffix, set1)(0.5),
+                    ),
+                    img: $atan2(self.img, self.real),
+                }
+            }
+
+            /// Principal power, see `Complex64::powc`
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn powc(self, w: Self) -> Self {
+                let zero = paste_op!($suffix, setzero)();
+                let z = Self::new(self.real, paste_op!($suffix, add)(self.img, zero));
+                let p = w.cmul(z.log()).exp();
+                let origin = paste_op!($suffix, cmpeq)(self.abs_sq(), zero);
+                Self {
+                    real: paste_op!($suffix, andnot)(origin, p.real),
+                    img: paste_op!($suffix, andnot)(origin, p.img),
+                }
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn sin(self) -> Self {
+                let (sin, cos) = $sin_cos(self.real);
+                let (sinh, cosh) = Self::sinh_cosh(self.img);
+                Self {
+                    real: paste_op!($suffix, mul)(sin, cosh),
+                    img: paste_op!($suffix, mul)(cos, sinh),
+                }
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn cos(self) -> Self {
+                let (sin, cos) = $sin_cos(self.real);
+                let (sinh, cosh) = Self::sinh_cosh(self.img);
+                Self {
+                    real: paste_op!($suffix, mul)(cos, cosh),
+                    img: paste_op!($suffix, sub)(
+                        paste_op!($suffix, setzero)(),
+                        paste_op!($suffix, mul)(sin, sinh),
+                    ),
+                }
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn sinh(self) -> Self {
+                let (sin, cos) = $sin_cos(self.img);
+                let (sinh, cosh) = Self::sinh_cosh(self.real);
+                Self {
+                    real: paste_op!($suffix, mul)(sinh, cos),
+                    img: paste_op!($suffix, mul)(cosh, sin),
+                }
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            pub unsafe fn cosh(self) -> Self {
+                let (sin, cos) = $sin_cos(self.img);
+                let (sinh, cosh) = Self::sinh_cosh(self.real);
+                Self {
+                    real: paste_op!($suffix, mul)(cosh, cos),
+                    img: paste_op!($suffix, mul)(sinh, sin),
+                }
+            }
+
+            #[inline]
+            #[target_feature(enable = "avx,avx2")]
+            unsafe fn sinh_cosh(x: $vec) -> ($vec, $vec) {
+                let e = $exp(x);
+                let inv = paste_op!($suffix, div)(paste_op!($suffix, set1)(1.0), e);
+                let half = paste_op!($suffix, set1)(0.5);
+                // Near 0 the difference cancels, the Taylor series of sinh
+                // keeps the digits
+                let series = paste_op!($suffix, mul)(
+                    x,
+                    paste_op!($suffix, horner)(
+                        paste_op!($suffix, mul)(x, x),
+                        &[
+                            1.0 / 1307674368000.0,
+                            1.0 / 6227020800.0,
+                            1.0 / 39916800.0,
+                            1.0 / 362880.0,
+                            1.0 / 5040.0,
+                            1.0 / 120.0,
+                            1.0 / 6.0,
+                            1.0,
+                        ],
+                    ),
+                );
+                let small = paste_op!($suffix, cmplt)(
+                    paste_op!($suffix, andnot)(paste_op!($suffix, set1)(-0.0), x),
+                    half,
+                );
+                (
+                    paste_op!($suffix, blendv)(
+                        paste_op!($suffix, mul)(paste_op!($suffix, sub)(e, inv), half),
+                        series,
+                        small,
+                    ),
+                    paste_op!($suffix, mul)(paste_op!($suffix, add)(e, inv), half),
+                )
+            }
+        }
+    };
+}
+
+impl_complex_avx_for!(
+    Complex64x4,
+    x86_64::__m256d,
+    f64,
+    pd,
+    exp_pd,
+    log_pd,
+    sin_cos_pd,
+    atan2_pd
+);
+impl_complex_avx_for!(
+    Complex32x8,
+    x86_64::__m256,
+    f32,
+    ps,
+    exp_ps,
+    log_ps,
+    sin_cos_ps,
+    atan2_ps
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::complex::{Complex32, Complex64};
+
+    // The branch cut from both sides, the origin, arguments far from the
+    // unit circle, and exp arguments past where e^x overflows or underflows.
+    // The real parts of sin and cos and the imaginary part of exp stay
+    // within the range of `sin_cos_pd` and `sin_cos_ps`.
+    const POINTS: [(f64, f64); 22] = [
+        (0.0, 0.0),
+        (-0.0, -0.0),
+        (1.0, 0.0),
+        (-1.0, 0.0),
+        (-1.0, -0.0),
+        (-2.5, 0.0),
+        (-2.5, -0.0),
+        (-2.5, 1e-12),
+        (-2.5, -1e-12),
+        (0.0, 1.0),
+        (0.0, -1.0),
+        (0.3, -0.7),
+        (-1.2, 2.9),
+        (3.0, 4.0),
+        (1e-7, -3e-8),
+        (1e5, 0.5),
+        (0.5, -1e5),
+        (20.0, -30.0),
+        (85.0, 1.0),
+        (-85.0, 1.0),
+        (800.0, 0.25),
+        (-800.0, 0.25),
+    ];
+
+    // Beyond the range of sin and cos, for the functions that do not take them
+    // and whose |z|^2 is finite in f32
+    const FAR: [(f64, f64); 4] = [(1e7, -1e7), (-4e15, 3e15), (-1e18, 0.0), (-1e18, -0.0)];
+
+    // Whether `got` is within `tol` of `want` relative to `scale`, the
+    // magnitude of the whole complex result. Infinities and NaNs must match.
+    fn close(got: f64, want: f64, scale: f64, tol: f64) -> bool {
+        if want.is_nan() {
+            got.is_nan()
+        } else if want.is_infinite() {
+            got == want
+        } else {
+            (got - want).abs() <= tol * scale.min(f64::MAX) + f64::MIN_POSITIVE
+        }
+    }
+
+    macro_rules! lanes_test {
+        ($name:ident, $class:ident, $suffix:ident, $scalar:ident, $complex:ident, $lanes:expr, $tol:expr) => {
+            #[test]
+            fn $name() {
+                if !is_x86_feature_detected!("avx2") {
+                    return;
+                }
+                let all: Vec<(f64, f64)> = POINTS.iter().chain(&FAR).copied().collect();
+                type Op<'a> = (
+                    &'static str,
+                    &'a [(f64, f64)],
+                    fn($complex) -> $complex,
+                    unsafe fn($class) -> $class,
+                );
+                let ops: [Op; 6] = [
+                    ("exp", &POINTS, $complex::exp, |z| unsafe { z.exp() }),
+                    ("log", &all, $complex::log, |z| unsafe { z.log() }),
+                    ("sin", &POINTS, $complex::sin, |z| unsafe { z.sin() }),
+                    ("cos", &POINTS, $complex::cos, |z| unsafe { z.cos() }),
+                    (
+                        "arg",
+                        &all,
+                        |z| $complex::new(z.arg(), 0.0),
+                        |z| unsafe { $class::new(z.arg(), paste_op!($suffix, setzero)()) },
+                    ),
+                    (
+                        "powc",
+                        &all,
+                        |z| z.powc($complex::new(2.5, -0.5)),
+                        |z| unsafe { z.powc($class::splat(2.5, -0.5)) },
+                    ),
+                ];
+                for (name, points, scalar, vector) in ops {
+                    for chunk in points.chunks($lanes) {
+                        let mut real = [0.0; $lanes];
+                        let mut img = [0.0; $lanes];
+                        for lane in 0..$lanes {
+                            let (re, im) = chunk[lane.min(chunk.len() - 1)];
+                            real[lane] = re as $scalar;
+                            img[lane] = im as $scalar;
+                        }
+                        let got = unsafe {
+                            vector($class::new(
+                                paste_op!($suffix, loadu)(real.as_ptr()),
+                                paste_op!($suffix, loadu)(img.as_ptr()),
+                            ))
+                        };
+                        let mut got_real = [0.0; $lanes];
+                        let mut got_img = [0.0; $lanes];
+                        unsafe {
+                            paste_op!($suffix, storeu)(got_real.as_mut_ptr(), got.real);
+                            paste_op!($suffix, storeu)(got_img.as_mut_ptr(), got.img);
+                        }
+                        for lane in 0..$lanes {
+                            let z = $complex::new(real[lane], img[lane]);
+                            let want = scalar(z);
+                            let scale = want.real.hypot(want.img) as f64;
+                            let ok = close(got_real[lane] as f64, want.real as f64, scale, $tol)
+                                && close(got_img[lane] as f64, want.img as f64, scale, $tol);
+                            assert!(
+                                ok,
+                                "{}({:?}) = ({}, {}) against ({}, {})",
+                                name, z, got_real[lane], got_img[lane], want.real, want.img
+                            );
+                        }
+                    }
+                }
+            }
+        };
+    }
+
+    lanes_test!(lanes_pd, Complex64x4, pd, f64, Complex64, 4, 1e-13);
+    lanes_test!(lanes_ps, Complex32x8, ps, f32, Complex32, 8, 1e-5);
+}
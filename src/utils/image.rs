@@ -0,0 +1,73 @@
+use std::ops::{Deref, DerefMut, Index, IndexMut};
+
+/// An RGB pixel, laid out like `pixel_canvas::Color`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// A rendered frame, row-major with row 0 at the bottom like
+/// `pixel_canvas::Image`, so the viewer copies it over as is
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+/// Row and column of a pixel of an `Image`
+#[derive(Clone, Copy, Debug)]
+pub struct RC(pub usize, pub usize);
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::default(); width * height],
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+impl Index<RC> for Image {
+    type Output = Color;
+
+    #[inline(always)]
+    fn index(&self, RC(row, col): RC) -> &Color {
+        &self.pixels[row * self.width + col]
+    }
+}
+
+impl IndexMut<RC> for Image {
+    #[inline(always)]
+    fn index_mut(&mut self, RC(row, col): RC) -> &mut Color {
+        &mut self.pixels[row * self.width + col]
+    }
+}
+
+impl Deref for Image {
+    type Target = [Color];
+
+    fn deref(&self) -> &[Color] {
+        &self.pixels
+    }
+}
+
+impl DerefMut for Image {
+    fn deref_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+}
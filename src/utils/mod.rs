@@ -1,2 +1,9 @@
 pub mod color;
 pub mod complex;
+#[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+pub mod complex_avx;
+pub mod fixed;
+pub mod image;
+#[cfg(feature = "export-png")]
+pub mod png;
+pub mod ppm;
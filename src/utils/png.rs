@@ -0,0 +1,21 @@
+use super::image::Image;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Write `image` as an 8-bit RGB PNG. Rows of an `Image` count up from the
+/// bottom, a PNG starts at the top.
+pub fn write(path: impl AsRef<Path>, image: &Image) -> std::io::Result<()> {
+    let out = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(out, image.width() as u32, image.height() as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut data = Vec::with_capacity(image.len() * 3);
+    for row in image.chunks(image.width().max(1)).rev() {
+        for color in row {
+            data.extend_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
@@ -0,0 +1,17 @@
+use super::image::Image;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Write `image` as a binary (P6) PPM. Rows of an `Image` count up from the
+/// bottom, a PPM starts at the top.
+pub fn write(path: impl AsRef<Path>, image: &Image) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "P6\n{} {}\n255\n", image.width(), image.height())?;
+    for row in image.chunks(image.width().max(1)).rev() {
+        for color in row {
+            out.write_all(&[color.r, color.g, color.b])?;
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,188 @@
+use fractals::{
+    AbsVariant, Antialias, Block, Color, ColorMode, Fractal, FractalKernel, FractalType, Image,
+    InstructionSet, JuliaMode, Precision, RenderContext, Trap, RC,
+};
+
+#[test]
+fn color_modes_without_kernels_render_iterations() {
+    let fractal = Fractal::new()
+        .set_fractal(FractalType::BurningShip)
+        .set_color_mode(ColorMode::OrbitTrap(Trap::Point(0.0, 0.0)));
+    assert!(matches!(fractal.get_color_mode(), ColorMode::OrbitTrap(_)));
+    let fractal = fractal.set_color_mode(ColorMode::Lighting(0.8, 1.5));
+    assert!(matches!(fractal.get_color_mode(), ColorMode::Iteration));
+    let fractal = fractal.set_fractal(FractalType::Julia);
+    assert!(matches!(fractal.get_color_mode(), ColorMode::Lighting(..)));
+    let fractal = fractal.set_fractal(FractalType::AbsVariation(AbsVariant::Tricorn));
+    assert!(matches!(fractal.get_color_mode(), ColorMode::Iteration));
+    let fractal = fractal.set_color_mode(ColorMode::Histogram(0.5));
+    assert!(matches!(fractal.get_color_mode(), ColorMode::Histogram(_)));
+}
+
+#[test]
+fn mandelbrot_in_julia_mode_renders_julia() {
+    for prec in [Precision::F32, Precision::F64, Precision::Fixed] {
+        let render = |fractal_type| {
+            let mut fractal = Fractal::new()
+                .set_iset(InstructionSet::detect())
+                .set_window_dim(45, 24)
+                .set_prec(prec)
+                .set_julia(JuliaMode::Fixed(-0.8, 0.156))
+                .set_fractal(fractal_type);
+            fractal.render(0, 0)
+        };
+        let julia = render(FractalType::Julia);
+        assert_eq!(render(FractalType::Mandelbrot), julia, "{:?}", prec);
+    }
+}
+
+// A frame of the Mandelbrot set with the edge of the set crossing it, on
+// one thread or on the pool, with the widest kernels the CPU runs
+fn frame(threads: Option<u8>, antialias: Antialias) -> Image {
+    let mut fractal = Fractal::new()
+        .set_iset(InstructionSet::detect())
+        .set_window_dim(45, 24)
+        .set_antialias(antialias);
+    if let Some(threads) = threads {
+        fractal = fractal.set_num_threads(threads);
+    }
+    fractal.render(0, 0)
+}
+
+// Whether a pixel differs from one of its four neighbours by more than
+// `threshold` in some channel
+fn edge(image: &Image, row: usize, col: usize, threshold: u8) -> bool {
+    let color = image[RC(row, col)];
+    let differs = |other: Color| {
+        color.r.abs_diff(other.r) > threshold
+            || color.g.abs_diff(other.g) > threshold
+            || color.b.abs_diff(other.b) > threshold
+    };
+    (row > 0 && differs(image[RC(row - 1, col)]))
+        || (row + 1 < image.height() && differs(image[RC(row + 1, col)]))
+        || (col > 0 && differs(image[RC(row, col - 1)]))
+        || (col + 1 < image.width() && differs(image[RC(row, col + 1)]))
+}
+
+#[test]
+fn adaptive_refinement_resamples_edges_only() {
+    for threads in [None, Some(3)] {
+        let plain = frame(threads, Antialias::Off);
+        let supersampled = frame(threads, Antialias::Supersample(3));
+        assert_eq!(frame(threads, Antialias::Adaptive(3, 255)), plain);
+        for threshold in [0, 40] {
+            let refined = frame(threads, Antialias::Adaptive(3, threshold));
+            let mut edges = 0;
+            for row in 0..plain.height() {
+                for col in 0..plain.width() {
+                    let want = if edge(&plain, row, col, threshold) {
+                        edges += 1;
+                        supersampled[RC(row, col)]
+                    } else {
+                        plain[RC(row, col)]
+                    };
+                    assert_eq!(
+                        refined[RC(row, col)],
+                        want,
+                        "{:?} threads, threshold {}, pixel ({}, {})",
+                        threads,
+                        threshold,
+                        row,
+                        col
+                    );
+                }
+            }
+            assert!(edges > 0 && edges < plain.len());
+        }
+    }
+}
+
+#[test]
+fn progressive_passes_end_on_the_full_frame() {
+    let mut fractal = Fractal::new()
+        .set_window_dim(45, 24)
+        .set_num_threads(3)
+        .set_progressive(true);
+    let mut image = Image::new(45, 24);
+    let mut passes = vec![];
+    for _ in 0..5 {
+        fractal.draw(&mut image, 0, 0);
+        passes.push(image.clone());
+    }
+    // 24 rows take the passes over every 8th, 4th, 2nd and every pixel,
+    // then the frame is left alone
+    assert_ne!(passes[0], passes[3]);
+    assert_eq!(passes[3], passes[4]);
+    let mut fractal = fractal.set_progressive(false);
+    assert_eq!(passes[3], fractal.render(0, 0));
+}
+
+#[test]
+fn zero_threads_render_on_one() {
+    let mut fractal = Fractal::new().set_window_dim(45, 24).set_num_threads(0);
+    let image = fractal.render(0, 0);
+    let job = fractal.render_job(0, 0, |_, _| {});
+    assert_eq!(job.wait().as_deref(), Some(&*image));
+}
+
+#[test]
+fn frames_wait_for_the_job_on_the_pool() {
+    let mut fractal = Fractal::new().set_window_dim(45, 24).set_num_threads(3);
+    let job = fractal.render_job(0, 0, |_, _| {});
+    let image = fractal.render(0, 0);
+    assert_eq!(job.wait().as_deref(), Some(&*image));
+}
+
+#[test]
+fn frames_without_pixels_render_empty() {
+    for (width, height) in [(0, 24), (45, 0)] {
+        for threads in [None, Some(3)] {
+            for antialias in [Antialias::Off, Antialias::Supersample(3)] {
+                let mut fractal = Fractal::new()
+                    .set_window_dim(width, height)
+                    .set_color_mode(ColorMode::Histogram(0.5))
+                    .set_antialias(antialias);
+                if let Some(threads) = threads {
+                    fractal = fractal.set_num_threads(threads);
+                }
+                assert!(fractal.render(0, 0).is_empty());
+                let job = fractal.render_job(0, 0, |_, _| {});
+                assert_eq!(job.wait(), Some(vec![]));
+                let path = std::env::temp_dir().join(format!("empty-{}x{}.ppm", width, height));
+                fractal.export(&path, 0, 0).unwrap();
+                std::fs::remove_file(path).unwrap();
+            }
+        }
+    }
+}
+
+// Writes counts past `max_iter`
+struct Overshoot;
+
+impl FractalKernel for Overshoot {
+    fn render(&self, mut ctx: RenderContext) -> Block {
+        for row in 0..ctx.rows.len() {
+            for col in 0..ctx.width {
+                ctx.block[row][col] = ctx.max_iter + (col % 3) as u32;
+            }
+        }
+        (ctx.rows.start, ctx.rows.end)
+    }
+}
+
+#[test]
+fn counts_past_max_iter_color_as_max_iter() {
+    for threads in [None, Some(3)] {
+        let mut fractal = Fractal::new()
+            .set_window_dim(45, 24)
+            .set_color_mode(ColorMode::Histogram(0.5))
+            .register("overshoot", Precision::F64, InstructionSet::None, Overshoot)
+            .set_prec(Precision::F64)
+            .set_fractal(FractalType::Registered("overshoot".to_string()));
+        if let Some(threads) = threads {
+            fractal = fractal.set_num_threads(threads);
+        }
+        let image = fractal.render(0, 0);
+        assert!(image.iter().all(|&color| color == image[RC(0, 0)]));
+    }
+}
@@ -0,0 +1,222 @@
+use std::fs;
+use std::path::PathBuf;
+
+use fractals::{Fractal, FractalType, InstructionSet, JuliaMode, Precision};
+
+// Small frames of Mandelbrot, Julia and Burning Ship at every precision
+// and instruction set this CPU runs, and of Newton, against the counts in
+// tests/golden. There is one file per fractal and precision, written by the
+// scalar kernel, which every instruction set is held to. Kernels that round
+// like the scalar one match it exactly. Fused multiply-adds, and the f32
+// lanes of Burning Ship against its scalar kernel that computes in f64,
+// move the counts on the boundary, those frames may differ on as many
+// pixels as they were measured to. After a change that moves the counts on purpose, write the
+// files again with `GOLDEN_UPDATE=1 cargo test golden` and check the diff.
+
+// Not a multiple of any lane count, so every row ends in a partial block
+const WIDTH: usize = 61;
+const HEIGHT: usize = 40;
+const MAX_ITERATIONS: u16 = 200;
+
+// Pixels whose count may differ, of the 2440 of a frame. The frames are
+// fixed and both kinds of rounding are exact, so these are the counts
+// measured on an AVX-512 CPU: fused multiply-adds move 9 in Mandelbrot and
+// 24 in Julia at f32 and 36 in Burning Ship at f64, the f32 lanes of
+// Burning Ship move 95 without them and 91 with them.
+const FUSED_MANDELBROT_F32: usize = 9;
+const FUSED_JULIA_F32: usize = 24;
+const FUSED_BURNING_SHIP_F64: usize = 36;
+const BURNING_SHIP_F32: usize = 95;
+
+const ISETS: [InstructionSet; 5] = [
+    InstructionSet::None,
+    InstructionSet::SSE,
+    InstructionSet::AVX,
+    InstructionSet::AVX512,
+    InstructionSet::Portable,
+];
+
+const PRECISIONS: [Precision; 3] = [Precision::F32, Precision::F64, Precision::Fixed];
+
+// Whether this CPU runs the kernels picked for `iset`, the others render
+// scalar where the build has no kernels for them
+fn supported(iset: InstructionSet) -> bool {
+    #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+    match iset {
+        InstructionSet::AVX => return is_x86_feature_detected!("avx2"),
+        InstructionSet::AVX512 => return is_x86_feature_detected!("avx512f"),
+        _ => {}
+    }
+    let _ = iset;
+    true
+}
+
+// Whether the kernels picked for `iset` fuse multiply-adds on this CPU
+fn fused(iset: InstructionSet) -> bool {
+    #[cfg(all(target_arch = "x86_64", feature = "simd-x86"))]
+    match iset {
+        InstructionSet::AVX => return is_x86_feature_detected!("fma"),
+        InstructionSet::AVX512 => return true,
+        _ => {}
+    }
+    let _ = iset;
+    false
+}
+
+fn tolerance(fractal: &FractalType, prec: Precision, iset: InstructionSet) -> usize {
+    match (fractal, prec) {
+        (FractalType::BurningShip, Precision::F32) if iset != InstructionSet::None => {
+            BURNING_SHIP_F32
+        }
+        _ if !fused(iset) => 0,
+        (FractalType::Mandelbrot, Precision::F32) => FUSED_MANDELBROT_F32,
+        (FractalType::Julia, Precision::F32) => FUSED_JULIA_F32,
+        (FractalType::BurningShip, Precision::F64) => FUSED_BURNING_SHIP_F64,
+        _ => 0,
+    }
+}
+
+// Julia and Newton read a fixed seed rather than the mouse
+fn render(
+    fractal: FractalType,
+    julia: JuliaMode,
+    prec: Precision,
+    iset: InstructionSet,
+) -> Vec<u32> {
+    Fractal::new()
+        .set_window_dim(WIDTH as u16, HEIGHT as u16)
+        .set_max_iter(MAX_ITERATIONS)
+        .set_pow(2)
+        .set_julia(julia)
+        .set_prec(prec)
+        .set_iset(iset)
+        .set_fractal(fractal)
+        .render_iterations(0, 0)
+}
+
+fn path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+        .iter()
+        .collect()
+}
+
+// A header with the dimensions, then a line of counts for every row
+fn write(name: &str, counts: &[u32]) {
+    let mut text = format!("{} {}\n", WIDTH, HEIGHT);
+    for row in counts.chunks(WIDTH) {
+        let row: Vec<String> = row.iter().map(u32::to_string).collect();
+        text.push_str(&row.join(" "));
+        text.push('\n');
+    }
+    fs::create_dir_all(path(name).parent().unwrap()).unwrap();
+    fs::write(path(name), text).unwrap();
+}
+
+fn read(name: &str) -> Vec<u32> {
+    let text = fs::read_to_string(path(name)).unwrap_or_else(|err| {
+        panic!("{}: {}, write it with GOLDEN_UPDATE=1", name, err);
+    });
+    let mut lines = text.lines();
+    assert_eq!(
+        lines.next(),
+        Some(format!("{} {}", WIDTH, HEIGHT).as_str()),
+        "{}: the frame size changed, write it again with GOLDEN_UPDATE=1",
+        name
+    );
+    lines
+        .flat_map(str::split_whitespace)
+        .map(|count| count.parse().unwrap())
+        .collect()
+}
+
+fn update() -> bool {
+    std::env::var_os("GOLDEN_UPDATE").is_some()
+}
+
+fn compare(case: &str, golden: &[u32], counts: &[u32], allowed: usize) {
+    assert_eq!(golden.len(), counts.len(), "{}", case);
+    let differ: Vec<usize> = (0..counts.len())
+        .filter(|&i| golden[i] != counts[i])
+        .collect();
+    if let Some(&first) = differ.first() {
+        assert!(
+            differ.len() <= allowed,
+            "{}: {} of {} pixels differ, {} allowed. First at ({}, {}), {} against {}",
+            case,
+            differ.len(),
+            counts.len(),
+            allowed,
+            first % WIDTH,
+            first / WIDTH,
+            counts[first],
+            golden[first]
+        );
+    }
+}
+
+fn check(name: &str, fractal: FractalType, precisions: &[Precision], julia: JuliaMode) {
+    for &prec in precisions {
+        let file = format!("{}_{:?}.txt", name, prec).to_lowercase();
+        if update() {
+            write(
+                &file,
+                &render(fractal.clone(), julia, prec, InstructionSet::None),
+            );
+        }
+        let golden = read(&file);
+        for iset in ISETS.into_iter().filter(|&iset| supported(iset)) {
+            let counts = render(fractal.clone(), julia, prec, iset);
+            let case = format!("{} {:?} {:?}", name, prec, iset);
+            compare(&case, &golden, &counts, tolerance(&fractal, prec, iset));
+        }
+    }
+}
+
+#[test]
+fn golden_mandelbrot() {
+    check(
+        "mandelbrot",
+        FractalType::Mandelbrot,
+        &PRECISIONS,
+        JuliaMode::Off,
+    );
+}
+
+#[test]
+fn golden_julia() {
+    check(
+        "julia",
+        FractalType::Julia,
+        &PRECISIONS,
+        JuliaMode::Fixed(-0.8, 0.156),
+    );
+}
+
+// Burning Ship has no fixed point kernel, `Precision::Fixed` renders at f64
+#[test]
+fn golden_burning_ship() {
+    let precisions = [Precision::F32, Precision::F64];
+    check(
+        "burning_ship",
+        FractalType::BurningShip,
+        &precisions,
+        JuliaMode::Off,
+    );
+}
+
+#[test]
+fn golden_newton() {
+    let name = "newton.txt";
+    let render = || {
+        render(
+            FractalType::Newton,
+            JuliaMode::Fixed(1.0, 0.0),
+            Precision::F32,
+            InstructionSet::None,
+        )
+    };
+    if update() {
+        write(name, &render());
+    }
+    compare("newton", &read(name), &render(), 0);
+}